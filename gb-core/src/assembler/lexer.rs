@@ -1,7 +1,6 @@
 use crate::cpu::instruction::{
     DerefTarget, IncDecTarget, JumpTest, Reg16Bit, Source8Bit, StackTarget, Target8Bit,
 };
-use env_logger::Target;
 use std::iter::Peekable;
 use std::str::Chars;
 
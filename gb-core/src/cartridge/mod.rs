@@ -4,6 +4,7 @@ use crate::mbc::FileBackedMBC;
 use crate::mbc::MBC;
 use std::path::PathBuf;
 use crate::cartridge::licensee::Licensee;
+use crate::gbs::GbsHeader;
 
 pub fn has_battery(cartridge_type: u8) -> bool {
     match cartridge_type {
@@ -48,11 +49,20 @@ impl Cartridge {
         self.mbc.write_ram(address, value)
     }
 
+    pub fn step(&mut self, cycles: u32) {
+        self.mbc.step(cycles)
+    }
+
     pub fn get_title(&self) -> String {
         let title = &self.mbc.get_rom()[0x134..0x143];
         title.iter().take_while(|&&c| c != 0).map(|&c| c as char).collect()
     }
 
+    /// The header of the GBS file this cartridge was built from, None for regular ROMs.
+    pub fn gbs_header(&self) -> Option<GbsHeader> {
+        GbsHeader::from_rom(self.mbc.get_rom())
+    }
+
     pub fn get_manufacturer_code(&self) -> String {
         let code = &self.mbc.get_rom()[0x13F..0x142];
         code.iter().map(|&c| c as char).collect()
@@ -139,4 +149,16 @@ impl Cartridge {
     pub fn get_global_checksum(&self) -> u16 {
         (self.mbc.get_rom()[0x14E] as u16) << 8 | self.mbc.get_rom()[0x14F] as u16
     }
+
+    /// CRC-32 of the whole ROM, identifies the exact ROM a movie was recorded with.
+    pub fn compute_crc32(&self) -> u32 {
+        let mut crc = 0xFFFFFFFFu32;
+        for &byte in self.mbc.get_rom() {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
 }
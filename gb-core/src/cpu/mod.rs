@@ -3,10 +3,12 @@ use crate::cpu::instruction::{
     DerefTarget, IncDecTarget, Instruction, JumpTest, LoadType, Reg16Bit, Source8Bit, StackTarget, Target8Bit,
 };
 use crate::cpu::register::Registers;
+use crate::debugger::{DebugEvent, Exception};
 use crate::gbmode::GbMode;
 use crate::io::sound::AudioPlayer;
 use crate::mbc::MBC;
 use crate::mmu::MMU;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 pub mod instruction;
 mod register;
@@ -165,6 +167,16 @@ fn is_set(byte: u8, position: u8) -> bool {
     (byte >> position) & 1 == 1
 }
 
+// WRAM or HRAM, the top of each included for an empty stack
+fn is_stack_address(sp: u16) -> bool {
+    (0xC000..=0xE000).contains(&sp) || sp >= 0xFF80
+}
+
+// ROM or HRAM
+fn is_code_address(address: u16) -> bool {
+    address <= 0x7FFF || (0xFF80..=0xFFFE).contains(&address)
+}
+
 pub struct Call {
     pub return_address: u16,
     pub caller_address: u16,
@@ -178,7 +190,12 @@ pub struct CPU {
     pub call_stack: Vec<Call>,
     ime: bool,
     is_halted: bool,
+    // Set by an illegal opcode, only a reset gets the CPU going again
+    locked_up: bool,
     gb_mode: GbMode,
+    pub instruction_count: u64, // Number of steps executed since power on, used to address points in time
+    pub cycle_count: u64,       // T-cycles executed since power on
+    power_on_state: Vec<u8>,
 }
 
 impl CPU {
@@ -188,14 +205,22 @@ impl CPU {
             _ => GbMode::Classic,
         };
         println!("Gb Mode: {:?}", gb_mode);
-        CPU {
+        let mut cpu = CPU {
             registers: Registers::new(gb_mode, using_boot_rom),
             mmu: MMU::new(cartridge, gb_mode, using_boot_rom, audio_player),
             call_stack: Vec::new(),
             ime: false,
             is_halted: false,
+            locked_up: false,
             gb_mode,
-        }
+            instruction_count: 0,
+            cycle_count: 0,
+            power_on_state: Vec::new(),
+        };
+        let mut writer = StateWriter::new();
+        cpu.save_state(&mut writer);
+        cpu.power_on_state = writer.into_bytes();
+        cpu
     }
 
     pub fn reset(&mut self) {
@@ -204,6 +229,53 @@ impl CPU {
         self.call_stack.clear();
         self.ime = false;
         self.is_halted = false;
+        self.locked_up = false;
+    }
+
+    /// Whether an illegal opcode hung the CPU, like it does on hardware.
+    pub fn is_locked_up(&self) -> bool {
+        self.locked_up
+    }
+
+    /// Puts the whole machine back into the state it had right after construction, unlike `reset`
+    /// which only restarts the cartridge and registers. The cartridge RAM is the one loaded at that time.
+    pub fn power_on(&mut self) {
+        let power_on_state = std::mem::take(&mut self.power_on_state);
+        self.load_state(&mut StateReader::new(&power_on_state));
+        self.power_on_state = power_on_state;
+    }
+
+    /// Calls the routine at `address` from outside the program, it returns to `return_address`.
+    /// Used to drive code that has no main loop of its own, such as GBS music drivers.
+    pub fn call(&mut self, address: u16, return_address: u16) {
+        self.push(return_address);
+        self.call_stack.push(Call {
+            return_address,
+            function_address: address,
+            caller_address: return_address,
+            stack_address: self.registers.sp,
+        });
+        self.registers.pc = address;
+        self.is_halted = false;
+    }
+
+    /// The instruction at `address` in assembly syntax, for debugger messages.
+    pub fn describe_instruction(&mut self, address: u16) -> String {
+        let mut opcode = self.mmu.read_byte(address);
+        let mut operand_address = address.wrapping_add(1);
+        let prefixed = opcode == 0xCB;
+        if prefixed {
+            opcode = self.mmu.read_byte(operand_address);
+            operand_address = operand_address.wrapping_add(1);
+        }
+        match Instruction::from_byte(opcode, prefixed) {
+            Some(instruction) => {
+                let byte1 = self.mmu.read_byte(operand_address);
+                let byte2 = self.mmu.read_byte(operand_address.wrapping_add(1));
+                instruction.to_string(byte1, byte2, address)
+            }
+            None => format!("DB   ${:02X}", opcode),
+        }
     }
 
     pub fn get_current_bank(&self) -> u8 {
@@ -245,6 +317,14 @@ impl CPU {
     }
 
     pub fn step(&mut self) -> u8 {
+        if self.locked_up {
+            // The rest of the hardware keeps running, but the CPU no longer fetches or takes interrupts
+            self.mmu.step(4);
+            self.instruction_count += 1;
+            self.cycle_count += 4;
+            return 4;
+        }
+
         // println!("Executing instruction at ${:04X}", self.registers.pc);
         let mut opcode = self.mmu.read_byte(self.registers.pc);
         let prefixed = opcode == 0xCB;
@@ -252,13 +332,28 @@ impl CPU {
             opcode = self.mmu.read_byte(self.registers.pc.wrapping_add(1));
         }
 
+        let checks = self.mmu.debug_events.checks;
+        let (pc, sp) = (self.registers.pc, self.registers.sp);
         let (next_pc, mut cycles) = if let Some(instruction) = Instruction::from_byte(opcode, prefixed) {
-            self.execute(instruction)
+            if !self.mmu.watchpoints.is_empty() || checks.any_enabled() {
+                self.mmu.watch_pc = Some(self.registers.pc);
+            }
+            let result = self.execute(instruction);
+            self.mmu.watch_pc = None;
+            result
         } else {
-            panic!("Invalid opcode: ${:02X}, PC: ${:04X}", opcode, self.registers.pc);
+            self.locked_up = true;
+            self.mmu.debug_events.push_always(DebugEvent::IllegalOpcode {
+                opcode,
+                bank: self.get_current_bank(),
+                address: self.registers.pc,
+            });
+            // PC stays on the opcode so a debugger shows where the crash happened, and no interrupt is taken
+            return self.step();
         };
 
         self.mmu.step(cycles as u32);
+        self.instruction_count += 1;
         if self.mmu.has_interrupt() {
             self.is_halted = false;
         }
@@ -296,10 +391,25 @@ impl CPU {
             cycles += 12;
         }
 
+        if checks.software_breakpoint && opcode == 0x40 && !prefixed {
+            self.mmu.debug_events.report(Exception::SoftwareBreakpoint);
+        }
+        // Only leaving the expected areas is reported, not every instruction outside them
+        if checks.stack_pointer && is_stack_address(sp) && !is_stack_address(self.registers.sp) {
+            self.mmu.debug_events.report(Exception::StackPointer { sp: self.registers.sp });
+        }
+        if checks.execution && is_code_address(pc) && !is_code_address(self.registers.pc) {
+            self.mmu.debug_events.report(Exception::Execution {
+                address: self.registers.pc,
+            });
+        }
+
+        self.cycle_count += cycles as u64;
         cycles
     }
 
     fn interrupt(&mut self, address: u16) {
+        self.mmu.debug_events.push(DebugEvent::Interrupt { vector: address });
         self.ime = false;
         self.push(self.registers.pc);
         self.call_stack.push(
@@ -684,6 +794,10 @@ impl CPU {
                 (self.registers.pc.wrapping_add(1), 4)
             }
             Instruction::HALT => {
+                self.mmu.debug_events.push(DebugEvent::Halt);
+                if self.mmu.debug_events.checks.hang && self.mmu.interrupt_enable & 0x1F == 0 {
+                    self.mmu.debug_events.report(Exception::Hang);
+                }
                 self.is_halted = true;
                 (self.registers.pc.wrapping_add(1), 4)
             }
@@ -694,7 +808,10 @@ impl CPU {
                 self.registers.f.carry = true;
                 (self.registers.pc.wrapping_add(1), 4)
             }
-            Instruction::STOP => (self.registers.pc.wrapping_add(2), 4),
+            Instruction::STOP => {
+                self.mmu.debug_events.push(DebugEvent::Stop);
+                (self.registers.pc.wrapping_add(2), 4)
+            }
         }
     }
 
@@ -944,11 +1061,64 @@ impl CPU {
         value | mask
     }
 
+    // Operand fetches are not data accesses, so they do not trigger watchpoints
     fn read_next_byte(&mut self) -> u8 {
-        self.mmu.read_byte(self.registers.pc.wrapping_add(1))
+        let watch_pc = self.mmu.watch_pc.take();
+        let value = self.mmu.read_byte(self.registers.pc.wrapping_add(1));
+        self.mmu.watch_pc = watch_pc;
+        value
     }
 
     fn read_next_word(&mut self) -> u16 {
-        self.mmu.read_word(self.registers.pc.wrapping_add(1))
+        let watch_pc = self.mmu.watch_pc.take();
+        let value = self.mmu.read_word(self.registers.pc.wrapping_add(1));
+        self.mmu.watch_pc = watch_pc;
+        value
     }
 }
+
+impl SaveState for CPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.is_halted);
+        writer.write_bool(self.locked_up);
+        writer.write_u64(self.instruction_count);
+        writer.write_u64(self.cycle_count);
+        writer.write_u32(self.call_stack.len() as u32);
+        for call in &self.call_stack {
+            writer.write_u16(call.return_address);
+            writer.write_u16(call.caller_address);
+            writer.write_u16(call.function_address);
+            writer.write_u16(call.stack_address);
+        }
+        self.mmu.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.registers.load_state(reader);
+        self.ime = reader.read_bool();
+        self.is_halted = reader.read_bool();
+        self.locked_up = reader.read_bool();
+        self.instruction_count = reader.read_u64();
+        self.cycle_count = reader.read_u64();
+        let call_count = reader.read_u32();
+        self.call_stack.clear();
+        for _ in 0..call_count {
+            self.call_stack.push(Call {
+                return_address: reader.read_u16(),
+                caller_address: reader.read_u16(),
+                function_address: reader.read_u16(),
+                stack_address: reader.read_u16(),
+            });
+        }
+        self.mmu.load_state(reader);
+    }
+}
+
+/// A DMG running Blargg's cpu_instrs without audio, the machine the unit tests poke at.
+#[cfg(test)]
+pub(crate) fn test_cpu() -> CPU {
+    let rom = std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../roms/blargg/cpu_instrs.gb"));
+    CPU::new(Cartridge::new(rom), false, Box::new(crate::io::sound::NullPlayer::new()))
+}
@@ -1,4 +1,5 @@
 use crate::gbmode::GbMode;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 #[inline(always)]
 fn bit(condition: bool) -> u8 {
@@ -174,6 +175,26 @@ impl Registers {
     }
 }
 
+impl SaveState for Registers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.get_af());
+        writer.write_u16(self.get_bc());
+        writer.write_u16(self.get_de());
+        writer.write_u16(self.get_hl());
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.set_af(reader.read_u16());
+        self.set_bc(reader.read_u16());
+        self.set_de(reader.read_u16());
+        self.set_hl(reader.read_u16());
+        self.sp = reader.read_u16();
+        self.pc = reader.read_u16();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,265 @@
+/// Hardware events a debugger can break on, reported by the CPU and MMU while `DebugEvents` is enabled.
+/// Illegal opcodes are always reported, exceptions whenever their check is enabled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DebugEvent {
+    /// The CPU jumped to an interrupt vector
+    Interrupt {
+        vector: u16,
+    },
+    Halt,
+    Stop,
+    /// A write to the MBC selected another ROM bank
+    BankSwitch {
+        bank: u8,
+    },
+    LcdEnabled(bool),
+    OamDma {
+        source: u16,
+    },
+    Hdma {
+        source: u16,
+        destination: u16,
+    },
+    /// Any write to FF00-FF7F or IE
+    IoWrite {
+        address: u16,
+        value: u8,
+    },
+    /// The CPU locked up on an opcode that does not exist
+    IllegalOpcode {
+        opcode: u8,
+        bank: u8,
+        address: u16,
+    },
+    Exception(Exception),
+}
+
+/// Likely bugs in the running code, found by `ExceptionChecks`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Exception {
+    /// A ROM write that does not reach an MBC register
+    RomWrite { address: u16, value: u8 },
+    /// A WRAM read of a byte that was never written since power on
+    UninitializedRead { address: u16 },
+    /// The stack pointer moved out of WRAM and HRAM
+    StackPointer { sp: u16 },
+    /// The PC moved out of ROM and HRAM
+    Execution { address: u16 },
+    /// A VRAM write while the PPU reads it in mode 3, which the hardware ignores
+    VramWrite { address: u16 },
+    /// HALT while IE is 0, which never wakes up
+    Hang,
+    /// `ld b,b`, used as a breakpoint by homebrew and test ROMs
+    SoftwareBreakpoint,
+}
+
+impl Exception {
+    pub fn describe(&self) -> String {
+        match *self {
+            Exception::RomWrite { address, value } => format!("Write ${:02X} to ROM at ${:04X}", value, address),
+            Exception::UninitializedRead { address } => format!("Read of uninitialized WRAM at ${:04X}", address),
+            Exception::StackPointer { sp } => format!("Stack pointer left WRAM and HRAM: ${:04X}", sp),
+            Exception::Execution { address } => format!("Execution outside ROM and HRAM at ${:04X}", address),
+            Exception::VramWrite { address } => format!("VRAM write during mode 3 at ${:04X}", address),
+            Exception::Hang => "HALT with IE = 0 hangs forever".to_owned(),
+            Exception::SoftwareBreakpoint => "Software breakpoint (ld b,b)".to_owned(),
+        }
+    }
+}
+
+/// Which `Exception`s are looked for, each off by default.
+#[derive(Default, Clone, Copy)]
+pub struct ExceptionChecks {
+    pub rom_write: bool,
+    pub uninitialized_read: bool,
+    pub stack_pointer: bool,
+    pub execution: bool,
+    pub vram_write: bool,
+    pub hang: bool,
+    pub software_breakpoint: bool,
+}
+
+impl ExceptionChecks {
+    pub fn any_enabled(&self) -> bool {
+        self.rom_write
+            || self.uninitialized_read
+            || self.stack_pointer
+            || self.execution
+            || self.vram_write
+            || self.hang
+            || self.software_breakpoint
+    }
+}
+
+impl DebugEvent {
+    pub fn describe(&self) -> String {
+        match *self {
+            DebugEvent::Interrupt { vector } => {
+                let name = match vector {
+                    0x40 => "VBlank",
+                    0x48 => "LCD STAT",
+                    0x50 => "Timer",
+                    0x58 => "Serial",
+                    _ => "Joypad",
+                };
+                format!("{} interrupt (${:04X})", name, vector)
+            }
+            DebugEvent::Halt => "HALT".to_owned(),
+            DebugEvent::Stop => "STOP".to_owned(),
+            DebugEvent::BankSwitch { bank } => format!("Switched to ROM bank ${:02X}", bank),
+            DebugEvent::LcdEnabled(true) => "LCD turned on".to_owned(),
+            DebugEvent::LcdEnabled(false) => "LCD turned off".to_owned(),
+            DebugEvent::OamDma { source } => format!("OAM DMA from ${:04X}", source),
+            DebugEvent::Hdma { source, destination } => {
+                format!("HDMA from ${:04X} to ${:04X}", source, destination)
+            }
+            DebugEvent::IoWrite { address, value } => format!("Write ${:02X} to ${:04X}", value, address),
+            DebugEvent::IllegalOpcode { opcode, bank, address } => {
+                format!("Illegal opcode ${:02X} at {:02X}:{:04X}", opcode, bank, address)
+            }
+            DebugEvent::Exception(exception) => exception.describe(),
+        }
+    }
+}
+
+/// The events of the current instruction. Nothing is collected while disabled, so emulation without a
+/// debugger attached pays nothing for it.
+#[derive(Default)]
+pub struct DebugEvents {
+    pub enabled: bool,
+    pub checks: ExceptionChecks,
+    events: Vec<DebugEvent>,
+}
+
+impl DebugEvents {
+    pub fn new() -> DebugEvents {
+        DebugEvents::default()
+    }
+
+    pub fn push(&mut self, event: DebugEvent) {
+        if self.enabled {
+            self.events.push(event);
+        }
+    }
+
+    /// Records the event even while disabled, for the few that always need attention.
+    pub fn push_always(&mut self, event: DebugEvent) {
+        self.events.push(event);
+    }
+
+    /// Records an exception found by an enabled check, whether or not other events are collected.
+    pub fn report(&mut self, exception: Exception) {
+        self.events.push(DebugEvent::Exception(exception));
+    }
+
+    /// Takes the events collected since the last call.
+    pub fn take(&mut self) -> Vec<DebugEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+    use crate::savestate::{SaveState, StateReader, StateWriter};
+
+    #[test]
+    fn writes_report_events_while_enabled() {
+        let mut cpu = test_cpu();
+        cpu.mmu.write_byte(0x2000, 2);
+        assert!(cpu.mmu.debug_events.take().is_empty());
+
+        cpu.mmu.debug_events.enabled = true;
+        cpu.mmu.write_byte(0x2000, 3);
+        cpu.mmu.write_byte(0x2000, 3);
+        cpu.mmu.write_byte(0xFF40, 0x11);
+        cpu.mmu.write_byte(0xFF46, 0xC1);
+        assert_eq!(
+            cpu.mmu.debug_events.take(),
+            vec![
+                DebugEvent::BankSwitch { bank: 3 },
+                DebugEvent::LcdEnabled(false),
+                DebugEvent::IoWrite {
+                    address: 0xFF40,
+                    value: 0x11
+                },
+                DebugEvent::OamDma { source: 0xC100 },
+                DebugEvent::IoWrite {
+                    address: 0xFF46,
+                    value: 0xC1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn illegal_opcode_locks_up() {
+        let mut cpu = test_cpu();
+        cpu.registers.pc = 0xC000;
+        cpu.mmu.write_byte(0xC000, 0xDD);
+        cpu.step();
+        cpu.step();
+        assert!(cpu.is_locked_up());
+        assert_eq!(cpu.registers.pc, 0xC000);
+        assert_eq!(
+            cpu.mmu.debug_events.take(),
+            vec![DebugEvent::IllegalOpcode {
+                opcode: 0xDD,
+                bank: 0x81,
+                address: 0xC000
+            }]
+        );
+
+        cpu.reset();
+        assert!(!cpu.is_locked_up());
+    }
+
+    #[test]
+    fn enabled_checks_report_exceptions() {
+        let mut cpu = test_cpu();
+        // ld b,b / ld a,[$C100] / ld sp,$8000
+        for (offset, byte) in [0x40, 0xFA, 0x00, 0xC1, 0x31, 0x00, 0x80].into_iter().enumerate() {
+            cpu.mmu.write_byte(0xC000 + offset as u16, byte);
+        }
+        cpu.registers.pc = 0xC000;
+        cpu.mmu.debug_events.checks.software_breakpoint = true;
+        cpu.mmu.debug_events.checks.uninitialized_read = true;
+        cpu.mmu.debug_events.checks.stack_pointer = true;
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            cpu.mmu.debug_events.take(),
+            vec![
+                DebugEvent::Exception(Exception::SoftwareBreakpoint),
+                DebugEvent::Exception(Exception::UninitializedRead { address: 0xC100 }),
+                DebugEvent::Exception(Exception::StackPointer { sp: 0x8000 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn uninitialized_reads_follow_savestates_and_skip_dma() {
+        let mut cpu = test_cpu();
+        // ld a,$C1 / ldh [$46],a / ld a,[$C100]
+        for (offset, byte) in [0x3E, 0xC1, 0xE0, 0x46, 0xFA, 0x00, 0xC1].into_iter().enumerate() {
+            cpu.mmu.write_byte(0xC000 + offset as u16, byte);
+        }
+        cpu.registers.pc = 0xC000;
+        let mut writer = StateWriter::new();
+        cpu.save_state(&mut writer);
+        let snapshot = writer.into_bytes();
+        cpu.mmu.write_byte(0xC100, 1);
+        cpu.load_state(&mut StateReader::new(&snapshot));
+
+        cpu.mmu.debug_events.checks.uninitialized_read = true;
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            cpu.mmu.debug_events.take(),
+            vec![DebugEvent::Exception(Exception::UninitializedRead { address: 0xC100 })]
+        );
+    }
+}
@@ -24,6 +24,12 @@ pub struct Disassembler {
     pub disassembly: Vec<Vec<DisassembledLine>>,
 }
 
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Disassembler {
     pub fn new() -> Self {
         let mut disassembly = Vec::new();
@@ -0,0 +1,548 @@
+use anyhow::{anyhow, Result};
+
+use crate::cpu::CPU;
+use crate::mbc::MBC;
+use crate::symbols::SymbolTable;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operand {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+    ZeroFlag,
+    SubtractFlag,
+    HalfCarryFlag,
+    CarryFlag,
+    Bank,
+}
+
+impl Operand {
+    fn from_name(name: &str) -> Option<Operand> {
+        let operand = match name.to_ascii_uppercase().as_str() {
+            "A" => Operand::A,
+            "B" => Operand::B,
+            "C" => Operand::C,
+            "D" => Operand::D,
+            "E" => Operand::E,
+            "F" => Operand::F,
+            "H" => Operand::H,
+            "L" => Operand::L,
+            "AF" => Operand::AF,
+            "BC" => Operand::BC,
+            "DE" => Operand::DE,
+            "HL" => Operand::HL,
+            "SP" => Operand::SP,
+            "PC" => Operand::PC,
+            "ZF" => Operand::ZeroFlag,
+            "NF" => Operand::SubtractFlag,
+            "HF" => Operand::HalfCarryFlag,
+            "CF" => Operand::CarryFlag,
+            "BANK" => Operand::Bank,
+            _ => return None,
+        };
+        Some(operand)
+    }
+
+    fn value(self, cpu: &CPU) -> i64 {
+        let registers = &cpu.registers;
+        let value = match self {
+            Operand::A => registers.a as u16,
+            Operand::B => registers.b as u16,
+            Operand::C => registers.c as u16,
+            Operand::D => registers.d as u16,
+            Operand::E => registers.e as u16,
+            Operand::F => registers.get_af() & 0xFF,
+            Operand::H => registers.h as u16,
+            Operand::L => registers.l as u16,
+            Operand::AF => registers.get_af(),
+            Operand::BC => registers.get_bc(),
+            Operand::DE => registers.get_de(),
+            Operand::HL => registers.get_hl(),
+            Operand::SP => registers.sp,
+            Operand::PC => registers.pc,
+            Operand::ZeroFlag => registers.f.zero as u16,
+            Operand::SubtractFlag => registers.f.subtract as u16,
+            Operand::HalfCarryFlag => registers.f.half_carry as u16,
+            Operand::CarryFlag => registers.f.carry as u16,
+            Operand::Bank => cpu.mmu.cartridge.mbc.get_selected_rom_bank() as u16,
+        };
+        value as i64
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UnaryOp {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOp {
+    // Binary operators from the loosest to the tightest binding
+    const PRECEDENCE: [&'static [(&'static str, BinaryOp)]; 10] = [
+        &[("||", BinaryOp::Or)],
+        &[("&&", BinaryOp::And)],
+        &[("|", BinaryOp::BitOr)],
+        &[("^", BinaryOp::BitXor)],
+        &[("&", BinaryOp::BitAnd)],
+        &[
+            ("==", BinaryOp::Equal),
+            ("=", BinaryOp::Equal),
+            ("!=", BinaryOp::NotEqual),
+        ],
+        &[
+            ("<", BinaryOp::Less),
+            ("<=", BinaryOp::LessEqual),
+            (">", BinaryOp::Greater),
+            (">=", BinaryOp::GreaterEqual),
+        ],
+        &[("<<", BinaryOp::ShiftLeft), (">>", BinaryOp::ShiftRight)],
+        &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+        &[
+            ("*", BinaryOp::Multiply),
+            ("/", BinaryOp::Divide),
+            ("%", BinaryOp::Remainder),
+        ],
+    ];
+
+    fn apply(self, left: i64, right: i64) -> i64 {
+        match self {
+            BinaryOp::Or => (left != 0 || right != 0) as i64,
+            BinaryOp::And => (left != 0 && right != 0) as i64,
+            BinaryOp::BitOr => left | right,
+            BinaryOp::BitXor => left ^ right,
+            BinaryOp::BitAnd => left & right,
+            BinaryOp::Equal => (left == right) as i64,
+            BinaryOp::NotEqual => (left != right) as i64,
+            BinaryOp::Less => (left < right) as i64,
+            BinaryOp::LessEqual => (left <= right) as i64,
+            BinaryOp::Greater => (left > right) as i64,
+            BinaryOp::GreaterEqual => (left >= right) as i64,
+            BinaryOp::ShiftLeft => left.wrapping_shl(right as u32),
+            BinaryOp::ShiftRight => left.wrapping_shr(right as u32),
+            BinaryOp::Add => left.wrapping_add(right),
+            BinaryOp::Subtract => left.wrapping_sub(right),
+            BinaryOp::Multiply => left.wrapping_mul(right),
+            BinaryOp::Divide => left.checked_div(right).unwrap_or(0),
+            BinaryOp::Remainder => left.checked_rem(right).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Node {
+    Constant(i64),
+    Operand(Operand),
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, cpu: &mut CPU) -> i64 {
+        match self {
+            Node::Constant(value) => *value,
+            Node::Operand(operand) => operand.value(cpu),
+            Node::Byte(address) => {
+                let address = address.evaluate(cpu) as u16;
+                cpu.mmu.read_byte(address) as i64
+            }
+            Node::Word(address) => {
+                let address = address.evaluate(cpu) as u16;
+                cpu.mmu.read_word(address) as i64
+            }
+            Node::Unary(op, value) => {
+                let value = value.evaluate(cpu);
+                match op {
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::Negate => value.wrapping_neg(),
+                    UnaryOp::Complement => !value,
+                }
+            }
+            Node::Binary(op, left, right) => {
+                let left = left.evaluate(cpu);
+                // Short-circuit so memory reads behind a failed PC check are skipped
+                match (op, left != 0) {
+                    (BinaryOp::And, false) => 0,
+                    (BinaryOp::Or, true) => 1,
+                    _ => op.apply(left, right.evaluate(cpu)),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 25] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "=", "<", ">", "+", "-", "*", "/", "%", "!", "~",
+    "(", ")", "[", "]",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if c == '$' || rest.starts_with("0x") || rest.starts_with("0X") {
+            let prefix = if c == '$' { 1 } else { 2 };
+            let digits = rest[prefix..].chars().take_while(|c| c.is_ascii_hexdigit()).count();
+            let value = i64::from_str_radix(&rest[prefix..prefix + digits], 16)
+                .map_err(|_| anyhow!("Invalid hexadecimal number at \"{}\"", rest))?;
+            tokens.push(Token::Number(value));
+            prefix + digits
+        } else if c.is_ascii_digit() {
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            tokens.push(Token::Number(rest[..digits].parse()?));
+            digits
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let length = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                .count();
+            tokens.push(Token::Identifier(rest[..length].to_owned()));
+            length
+        } else if c == '{' || c == '}' {
+            tokens.push(Token::Symbol(if c == '{' { "{" } else { "}" }));
+            1
+        } else {
+            let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) else {
+                return Err(anyhow!("Unexpected \"{}\"", c));
+            };
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.peek_symbol() != Some(symbol) {
+            return Err(anyhow!("Expected \"{}\"", symbol));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node> {
+        let Some(operators) = BinaryOp::PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(&(_, op)) = operators.iter().find(|(symbol, _)| Some(*symbol) == self.peek_symbol()) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Node::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        let op = match self.peek_symbol() {
+            Some("!") => UnaryOp::Not,
+            Some("-") => UnaryOp::Negate,
+            Some("~") => UnaryOp::Complement,
+            _ => return self.primary(),
+        };
+        self.position += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Node::Constant(value)),
+            Some(Token::Identifier(name)) => {
+                if let Some(operand) = Operand::from_name(&name) {
+                    Ok(Node::Operand(operand))
+                } else if let Some((_, address)) = self.symbols.get(&name) {
+                    Ok(Node::Constant(address as i64))
+                } else {
+                    Err(anyhow!("Unknown register or symbol \"{}\"", name))
+                }
+            }
+            Some(Token::Symbol("(")) => {
+                let node = self.binary(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Symbol("[")) => {
+                let node = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Node::Byte(Box::new(node)))
+            }
+            Some(Token::Symbol("{")) => {
+                let node = self.binary(0)?;
+                self.expect("}")?;
+                Ok(Node::Word(Box::new(node)))
+            }
+            Some(Token::Symbol(symbol)) => Err(anyhow!("Unexpected \"{}\"", symbol)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+/// An expression over the machine state like `PC == $4A10 && A == $3F && [wPlayerHP] < 10`.
+/// Registers and flags (ZF, NF, HF, CF) read by name, `BANK` is the selected ROM bank, `[x]` reads
+/// a byte and `{x}` a little-endian word from memory. Symbols are resolved to their address when parsed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Expression {
+    text: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<Expression> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            symbols,
+        };
+        let root = parser.binary(0)?;
+        if parser.position < parser.tokens.len() {
+            return Err(anyhow!("Unexpected {:?}", parser.tokens[parser.position]));
+        }
+        Ok(Expression {
+            text: text.trim().to_owned(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, cpu: &mut CPU) -> i64 {
+        self.root.evaluate(cpu)
+    }
+
+    pub fn is_true(&self, cpu: &mut CPU) -> bool {
+        self.evaluate(cpu) != 0
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// What a breakpoint needs on top of its trigger: an expression that has to hold, and the hit it starts
+/// breaking on.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct BreakCondition {
+    pub expression: Option<Expression>,
+    /// Counting from 1, hits before it are only counted
+    pub break_on_hit: u32,
+    pub hits: u32,
+}
+
+impl BreakCondition {
+    /// Parses the expression and the hit to break on, both may be empty.
+    pub fn parse(expression: &str, break_on_hit: &str, symbols: &SymbolTable) -> Result<BreakCondition> {
+        let expression = match expression.trim() {
+            "" => None,
+            text => Some(Expression::parse(text, symbols)?),
+        };
+        let break_on_hit = match break_on_hit.trim() {
+            "" => 1,
+            text => text.parse()?,
+        };
+        Ok(BreakCondition {
+            expression,
+            break_on_hit,
+            hits: 0,
+        })
+    }
+
+    /// Whether the expression holds, without counting a hit.
+    pub fn holds(&self, cpu: &mut CPU) -> bool {
+        self.expression
+            .as_ref()
+            .is_none_or(|expression| expression.is_true(cpu))
+    }
+
+    /// Counts a hit if the expression holds and returns whether to break on it.
+    pub fn check(&mut self, cpu: &mut CPU) -> bool {
+        if !self.holds(cpu) {
+            return false;
+        }
+        self.hits = self.hits.saturating_add(1);
+        self.hits >= self.break_on_hit
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+        if let Some(expression) = &self.expression {
+            text += &format!(" if {}", expression.text());
+        }
+        if self.break_on_hit > 1 {
+            text += &format!(" from hit {}", self.break_on_hit);
+        }
+        if self.hits > 0 {
+            text += &format!(" ({} hits)", self.hits);
+        }
+        text
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum LogPart {
+    Text(String),
+    Value { expression: Expression, decimal: bool },
+}
+
+/// A logpoint message like `enemy {B} hp={[HL]:d}`. Expressions in braces are printed in hexadecimal,
+/// or in decimal with a `:d` suffix.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LogFormat {
+    text: String,
+    parts: Vec<LogPart>,
+}
+
+impl LogFormat {
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<LogFormat> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(LogPart::Text(rest[..start].to_owned()));
+            }
+            // Braces nest, since `{addr}` reads a word inside an expression
+            let mut depth = 0;
+            let Some(end) = rest[start..].find(|c| {
+                depth += match c {
+                    '{' => 1,
+                    '}' => -1,
+                    _ => 0,
+                };
+                depth == 0
+            }) else {
+                return Err(anyhow!("Missing \"}}\" in \"{}\"", rest));
+            };
+            let inner = &rest[start + 1..start + end];
+            let (inner, decimal) = match inner.strip_suffix(":d") {
+                Some(inner) => (inner, true),
+                None => (inner.strip_suffix(":x").unwrap_or(inner), false),
+            };
+            parts.push(LogPart::Value {
+                expression: Expression::parse(inner, symbols)?,
+                decimal,
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(LogPart::Text(rest.to_owned()));
+        }
+        Ok(LogFormat {
+            text: text.to_owned(),
+            parts,
+        })
+    }
+
+    pub fn format(&self, cpu: &mut CPU) -> String {
+        let mut message = String::new();
+        for part in &self.parts {
+            match part {
+                LogPart::Text(text) => message += text,
+                LogPart::Value { expression, decimal } => {
+                    let value = expression.evaluate(cpu);
+                    message += &match value {
+                        _ if *decimal => value.to_string(),
+                        0..=0xFF => format!("${:02X}", value),
+                        0x100..=0xFFFF => format!("${:04X}", value),
+                        _ => value.to_string(),
+                    };
+                }
+            }
+        }
+        message
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    #[test]
+    fn parse_and_evaluate() {
+        let mut cpu = test_cpu();
+        cpu.registers.pc = 0x4A10;
+        cpu.registers.a = 0x3F;
+        cpu.registers.set_hl(0xC000);
+        cpu.mmu.write_byte(0xC000, 9);
+        cpu.mmu.write_byte(0xC001, 0x12);
+        let symbols = SymbolTable::parse("00:C000 wPlayerHP\n");
+
+        let parse = |text: &str| Expression::parse(text, &symbols).unwrap();
+        assert!(parse("PC=$4A10 && A==$3F && [wPlayerHP]<10").is_true(&mut cpu));
+        assert!(!parse("PC == $4A10 && a != 0x3F").is_true(&mut cpu));
+        assert_eq!(parse("{HL}").evaluate(&mut cpu), 0x1209);
+        assert_eq!(parse("1 + 2 * 3 << 1").evaluate(&mut cpu), 14);
+        assert_eq!(parse("-(2 - 5) | ~0 & 1").evaluate(&mut cpu), 3);
+        assert!(Expression::parse("[HL", &symbols).is_err());
+        assert!(Expression::parse("wEnemyHP > 0", &symbols).is_err());
+
+        let log = LogFormat::parse("hp={[wPlayerHP]:d} at {PC} word {{HL}}", &symbols).unwrap();
+        assert_eq!(log.format(&mut cpu), "hp=9 at $4A10 word $1209");
+        assert!(LogFormat::parse("{A", &symbols).is_err());
+
+        let mut condition = BreakCondition::parse("A == $3F", "3", &symbols).unwrap();
+        assert!(!condition.check(&mut cpu));
+        assert!(!condition.check(&mut cpu));
+        assert!(condition.check(&mut cpu));
+    }
+}
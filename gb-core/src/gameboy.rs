@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+
+use crate::cartridge::Cartridge;
+use crate::cpu::CPU;
+use crate::gbs::GbsPlayer;
+use crate::io::sound::AudioPlayer;
+
+#[derive(PartialEq, Debug)]
+pub enum HookAction {
+    Continue,
+    Break,
+}
+
+/// Observes execution through `GameBoy`, called around every instruction.
+pub trait DebugHook {
+    /// Called with PC on the next instruction, `HookAction::Break` stops before it is executed.
+    /// The CPU is mutable so hooks can read memory, see `MMU::read_byte`.
+    fn before_instruction(&mut self, _cpu: &mut CPU) -> HookAction {
+        HookAction::Continue
+    }
+
+    fn after_instruction(&mut self, _cpu: &CPU, _cycles: u8) {}
+}
+
+/// Front-end facing entry point: loads a ROM and runs it an instruction or a frame at a time.
+/// `cpu` gives debuggers full access to the machine. GBS music files load as well and play their songs.
+pub struct GameBoy {
+    pub cpu: CPU,
+    hooks: Vec<Box<dyn DebugHook>>,
+    gbs: Option<GbsPlayer>,
+}
+
+impl GameBoy {
+    pub fn new(rom_path: PathBuf, audio_player: Box<dyn AudioPlayer>) -> GameBoy {
+        GameBoy::from_cartridge(Cartridge::new(rom_path), false, audio_player)
+    }
+
+    pub fn from_cartridge(cartridge: Cartridge, using_boot_rom: bool, audio_player: Box<dyn AudioPlayer>) -> GameBoy {
+        let gbs = cartridge.gbs_header().map(GbsPlayer::new);
+        let mut gameboy = GameBoy {
+            cpu: CPU::new(cartridge, using_boot_rom, audio_player),
+            hooks: Vec::new(),
+            gbs,
+        };
+        gameboy.restart_song();
+        gameboy
+    }
+
+    /// The GBS player when a GBS file is loaded.
+    pub fn gbs(&self) -> Option<&GbsPlayer> {
+        self.gbs.as_ref()
+    }
+
+    /// Starts `song` (0-based) of a GBS file from the beginning.
+    pub fn play_song(&mut self, song: u8) {
+        if let Some(gbs) = &mut self.gbs {
+            gbs.start_song(&mut self.cpu, song);
+        }
+    }
+
+    pub fn next_song(&mut self) {
+        if let Some(gbs) = &mut self.gbs {
+            gbs.next_song(&mut self.cpu);
+        }
+    }
+
+    pub fn previous_song(&mut self) {
+        if let Some(gbs) = &mut self.gbs {
+            gbs.previous_song(&mut self.cpu);
+        }
+    }
+
+    fn restart_song(&mut self) {
+        if let Some(gbs) = &mut self.gbs {
+            gbs.start_song(&mut self.cpu, gbs.song());
+        }
+    }
+
+    pub fn add_debug_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn clear_debug_hooks(&mut self) {
+        self.hooks.clear();
+    }
+
+    /// Executes one instruction and returns the cycles it took, or None if a debug hook stopped it.
+    pub fn step_instruction(&mut self) -> Option<u8> {
+        if let Some(gbs) = &mut self.gbs {
+            gbs.before_instruction(&mut self.cpu);
+        }
+        let mut action = HookAction::Continue;
+        for hook in &mut self.hooks {
+            if hook.before_instruction(&mut self.cpu) == HookAction::Break {
+                action = HookAction::Break;
+            }
+        }
+        if action == HookAction::Break {
+            return None;
+        }
+
+        let cycles = self.cpu.step();
+        if let Some(gbs) = &mut self.gbs {
+            gbs.after_instruction(cycles);
+        }
+        for hook in &mut self.hooks {
+            hook.after_instruction(&self.cpu, cycles);
+        }
+        Some(cycles)
+    }
+
+    /// Runs until the PPU enters the next VBlank, so the framebuffer holds a complete frame.
+    /// Returns false if a debug hook stopped execution before that.
+    pub fn run_frame(&mut self) -> bool {
+        let frame = self.cpu.mmu.ppu.frame_count;
+        while self.cpu.mmu.ppu.frame_count == frame {
+            if self.step_instruction().is_none() {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.mmu.ppu.frame_count
+    }
+
+    /// The last rendered frame as RGB, `SCREEN_WIDTH` x `SCREEN_HEIGHT` pixels.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.cpu.mmu.ppu.screen_buffer
+    }
+
+    pub fn audio_player(&self) -> &dyn AudioPlayer {
+        self.cpu.mmu.sound.player()
+    }
+
+    pub fn audio_player_mut(&mut self) -> &mut dyn AudioPlayer {
+        self.cpu.mmu.sound.player_mut()
+    }
+
+    pub fn set_audio_player(&mut self, player: Box<dyn AudioPlayer>) {
+        self.cpu.mmu.sound.set_player(player);
+    }
+
+    /// Buttons currently held, see `Joypad::get_buttons` for the bit layout.
+    pub fn buttons(&self) -> u8 {
+        self.cpu.mmu.joypad.get_buttons()
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.cpu.mmu.joypad.set_buttons(buttons);
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.restart_song();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn gameboy() -> GameBoy {
+        GameBoy {
+            cpu: test_cpu(),
+            hooks: Vec::new(),
+            gbs: None,
+        }
+    }
+
+    // Breaks at `address` and counts the cycles of the instructions it saw executed
+    struct BreakAt {
+        address: u16,
+        cycles: Arc<AtomicU32>,
+    }
+
+    impl DebugHook for BreakAt {
+        fn before_instruction(&mut self, cpu: &mut CPU) -> HookAction {
+            if cpu.registers.pc == self.address {
+                HookAction::Break
+            } else {
+                HookAction::Continue
+            }
+        }
+
+        fn after_instruction(&mut self, _cpu: &CPU, cycles: u8) {
+            self.cycles.fetch_add(cycles as u32, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn step_instruction_and_run_frame() {
+        let mut gameboy = gameboy();
+        let cycles = gameboy.step_instruction().unwrap();
+        assert!(cycles > 0);
+        assert_eq!(gameboy.cpu.instruction_count, 1);
+
+        let frame = gameboy.frame_count();
+        assert!(gameboy.run_frame());
+        assert_eq!(gameboy.frame_count(), frame + 1);
+    }
+
+    #[test]
+    fn hooks_observe_and_break() {
+        let mut gameboy = gameboy();
+        let cycles = Arc::new(AtomicU32::new(0));
+        // The entry point of cpu_instrs is nop / jp $0637
+        gameboy.add_debug_hook(Box::new(BreakAt {
+            address: 0x0637,
+            cycles: cycles.clone(),
+        }));
+        assert!(!gameboy.run_frame());
+        assert_eq!(gameboy.cpu.registers.pc, 0x0637);
+        assert_eq!(gameboy.cpu.instruction_count, 2);
+        assert_eq!(cycles.load(Ordering::Relaxed) as u64, gameboy.cpu.cycle_count);
+        assert_eq!(gameboy.step_instruction(), None);
+
+        gameboy.clear_debug_hooks();
+        assert!(gameboy.step_instruction().is_some());
+    }
+}
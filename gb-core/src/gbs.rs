@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+
+use crate::cpu::CPU;
+use crate::tracker::CYCLES_PER_FRAME;
+
+// https://ocremix.org/info/GBS_Format_Specification
+pub const MAGIC: &[u8] = b"GBS";
+pub const HEADER_SIZE: usize = 0x70;
+
+/// Where the synthetic ROM keeps a copy of the header, so it can be found again from the cartridge.
+pub const HEADER_COPY_ADDRESS: usize = 0x0200;
+/// INIT and PLAY return here, to an idle loop that spins until the next PLAY call.
+pub const RETURN_ADDRESS: u16 = 0x0100;
+// Code below the lowest allowed load address belongs to the synthetic ROM
+const MIN_LOAD_ADDRESS: u16 = 0x0400;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GbsHeader {
+    pub version: u8,
+    pub song_count: u8,
+    /// 0-based, the file stores it 1-based
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl GbsHeader {
+    pub fn parse(data: &[u8]) -> Result<GbsHeader> {
+        if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) {
+            return Err(anyhow!("Not a GBS file"));
+        }
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let text = |offset: usize| {
+            data[offset..offset + 32]
+                .iter()
+                .take_while(|&&c| c != 0)
+                .map(|&c| c as char)
+                .collect::<String>()
+        };
+
+        let header = GbsHeader {
+            version: data[0x03],
+            song_count: data[0x04],
+            first_song: data[0x05].saturating_sub(1),
+            load_address: word(0x06),
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            title: text(0x10),
+            author: text(0x30),
+            copyright: text(0x50),
+        };
+        if header.song_count == 0 {
+            return Err(anyhow!("GBS file has no songs"));
+        }
+        if !(MIN_LOAD_ADDRESS..0x8000).contains(&header.load_address) {
+            return Err(anyhow!("Unsupported GBS load address ${:04X}", header.load_address));
+        }
+        Ok(header)
+    }
+
+    /// Finds the header in a ROM image built by `build_rom`.
+    pub fn from_rom(rom: &[u8]) -> Option<GbsHeader> {
+        GbsHeader::parse(rom.get(HEADER_COPY_ADDRESS..)?).ok()
+    }
+
+    /// Whether PLAY is driven by the timer rather than by VBlank.
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+
+    /// CPU cycles between PLAY calls.
+    pub fn play_period(&self) -> u32 {
+        if !self.uses_timer() {
+            return CYCLES_PER_FRAME as u32;
+        }
+        let cycles_per_tick = match self.timer_control & 0x03 {
+            0 => 1024,
+            1 => 16,
+            2 => 64,
+            _ => 256,
+        };
+        let period = (256 - self.timer_modulo as u32) * cycles_per_tick;
+        // Bit 7 asks for CGB double speed, which doubles the timer rate
+        if self.timer_control & 0x80 != 0 {
+            period / 2
+        } else {
+            period
+        }
+    }
+}
+
+/// Lays out the music data in a ROM image the way the GBS format expects it in memory: the data starts
+/// at the load address, bank 0 below it holds RST vectors jumping into the data, interrupt vectors that
+/// return immediately, the idle loop at `RETURN_ADDRESS` and a copy of the header.
+pub fn build_rom(data: &[u8]) -> Result<Vec<u8>> {
+    let header = GbsHeader::parse(data)?;
+    let music = &data[HEADER_SIZE..];
+    let end = header.load_address as usize + music.len();
+    let size = end.div_ceil(0x4000).max(2) * 0x4000;
+    let mut rom = vec![0xFF; size];
+
+    for vector in (0..0x40).step_by(8) {
+        let target = header.load_address + vector as u16;
+        rom[vector..vector + 3].copy_from_slice(&[0xC3, target as u8, (target >> 8) as u8]); // JP target
+    }
+    for vector in (0x40..=0x60).step_by(8) {
+        rom[vector] = 0xD9; // RETI
+    }
+    let idle = RETURN_ADDRESS as usize;
+    rom[idle..idle + 2].copy_from_slice(&[0x18, 0xFE]); // JR -2
+    // Plain DMG cartridge without an MBC in the header
+    rom[0x134..0x150].fill(0);
+    rom[HEADER_COPY_ADDRESS..HEADER_COPY_ADDRESS + HEADER_SIZE].copy_from_slice(&data[..HEADER_SIZE]);
+    rom[header.load_address as usize..end].copy_from_slice(music);
+    Ok(rom)
+}
+
+/// Drives a GBS music driver: INIT once per song, then PLAY at the rate the header asks for.
+/// The CPU sits in the idle loop at `RETURN_ADDRESS` between calls.
+pub struct GbsPlayer {
+    pub header: GbsHeader,
+    song: u8,
+    cycles_until_play: i64,
+}
+
+impl GbsPlayer {
+    pub fn new(header: GbsHeader) -> GbsPlayer {
+        GbsPlayer {
+            song: header.first_song,
+            header,
+            cycles_until_play: 0,
+        }
+    }
+
+    /// The song playing, 0-based.
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+
+    /// Powers the machine back on and calls INIT for `song`, wrapping around the song count.
+    pub fn start_song(&mut self, cpu: &mut CPU, song: u8) {
+        self.song = song % self.header.song_count;
+        self.cycles_until_play = self.header.play_period() as i64;
+
+        cpu.power_on();
+        cpu.mmu.write_byte(0xFF26, 0x80);
+        cpu.mmu.write_byte(0xFF25, 0xFF);
+        cpu.mmu.write_byte(0xFF24, 0x77);
+        cpu.mmu.write_byte(0xFF06, self.header.timer_modulo);
+        cpu.mmu.write_byte(0xFF07, self.header.timer_control);
+        cpu.registers.sp = self.header.stack_pointer;
+        cpu.registers.a = self.song;
+        cpu.call(self.header.init_address, RETURN_ADDRESS);
+    }
+
+    pub fn next_song(&mut self, cpu: &mut CPU) {
+        self.start_song(cpu, self.song.wrapping_add(1) % self.header.song_count);
+    }
+
+    pub fn previous_song(&mut self, cpu: &mut CPU) {
+        let song = self.song.checked_sub(1).unwrap_or(self.header.song_count - 1);
+        self.start_song(cpu, song);
+    }
+
+    /// Calls PLAY once the driver is idle and its period has elapsed.
+    pub fn before_instruction(&mut self, cpu: &mut CPU) {
+        if cpu.registers.pc == RETURN_ADDRESS && self.cycles_until_play <= 0 {
+            self.cycles_until_play += self.header.play_period() as i64;
+            cpu.call(self.header.play_address, RETURN_ADDRESS);
+        }
+    }
+
+    pub fn after_instruction(&mut self, cycles: u8) {
+        self.cycles_until_play -= cycles as i64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gbs_file(music: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[0..4].copy_from_slice(b"GBS\x01");
+        data[0x04] = 3;
+        data[0x05] = 2;
+        data[0x06..0x08].copy_from_slice(&0x3F00u16.to_le_bytes());
+        data[0x08..0x0A].copy_from_slice(&0x3F00u16.to_le_bytes());
+        data[0x0A..0x0C].copy_from_slice(&0x3F01u16.to_le_bytes());
+        data[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+        data[0x0E] = 0xC0;
+        data[0x0F] = 0x06;
+        data[0x10..0x14].copy_from_slice(b"Test");
+        data.extend_from_slice(music);
+        data
+    }
+
+    #[test]
+    fn header_and_rom_layout() {
+        let data = gbs_file(&[0xC9, 0xC9]);
+        let header = GbsHeader::parse(&data).unwrap();
+        assert_eq!((header.song_count, header.first_song), (3, 1));
+        assert_eq!(header.title, "Test");
+        // 64 timer ticks of 64 cycles each
+        assert_eq!(header.play_period(), 64 * 64);
+
+        let rom = build_rom(&data).unwrap();
+        assert_eq!(rom.len(), 0x8000);
+        assert_eq!(&rom[0x3F00..0x3F02], &[0xC9, 0xC9]);
+        assert_eq!(&rom[0x08..0x0B], &[0xC3, 0x08, 0x3F]);
+        assert_eq!(GbsHeader::from_rom(&rom), Some(header));
+
+        assert!(GbsHeader::parse(b"GBX").is_err());
+    }
+}
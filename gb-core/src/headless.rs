@@ -0,0 +1,176 @@
+//! Runs ROMs without a UI until they report a result, used by `gb-debug --headless` and the test ROM suite.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::cartridge::Cartridge;
+use crate::cpu::CPU;
+use crate::gameboy::{DebugHook, GameBoy, HookAction};
+use crate::io::sound::NullPlayer;
+use crate::screenshot::Screenshot;
+
+/// Condition that ends a headless run before the frame limit is reached.
+#[derive(Debug)]
+pub enum Completion {
+    // Mooneye tests execute `ld b, b` when done, with the Fibonacci numbers in B C D E H L on success
+    Mooneye,
+    // Blargg tests print their results to the serial port
+    Serial,
+    // The screen matches a known good hash, see `HeadlessRunner::screen_hash`
+    ScreenHash(u64),
+}
+
+impl Completion {
+    /// Parses "mooneye", "serial" or "hash:<hex>".
+    pub fn parse(text: &str) -> Result<Completion> {
+        match text {
+            "mooneye" => Ok(Completion::Mooneye),
+            "serial" => Ok(Completion::Serial),
+            _ => match text.strip_prefix("hash:") {
+                Some(hash) => Ok(Completion::ScreenHash(u64::from_str_radix(hash, 16)?)),
+                None => Err(anyhow!("Unknown completion condition: {}", text)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+/// Runs a ROM without any UI, for test ROMs and scripted runs.
+pub struct HeadlessRunner {
+    pub gameboy: GameBoy,
+    serial_output: Vec<u8>, // Everything the ROM sent, drained from the serial port as it arrives
+}
+
+const LD_B_B: u8 = 0x40;
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+// Stops before `ld b, b`, where mooneye tests are done
+struct MooneyeHook;
+
+impl DebugHook for MooneyeHook {
+    fn before_instruction(&mut self, cpu: &mut CPU) -> HookAction {
+        if cpu.mmu.read_byte(cpu.registers.pc) == LD_B_B {
+            HookAction::Break
+        } else {
+            HookAction::Continue
+        }
+    }
+}
+
+impl HeadlessRunner {
+    pub fn new(rom_path: PathBuf) -> HeadlessRunner {
+        let mut cartridge = Cartridge::new(rom_path);
+        // Runs must not depend on or leave behind save files
+        cartridge.mbc.persist_ram = false;
+        HeadlessRunner {
+            gameboy: GameBoy::from_cartridge(cartridge, false, Box::new(NullPlayer::new())),
+            serial_output: Vec::new(),
+        }
+    }
+
+    /// Runs until `frames` frames have been emulated, or until `completion` reports a result.
+    /// Without a completion condition reaching the frame limit counts as passed.
+    pub fn run(&mut self, frames: u64, completion: Option<&Completion>) -> Outcome {
+        if let Some(Completion::Mooneye) = completion {
+            self.gameboy.add_debug_hook(Box::new(MooneyeHook));
+        }
+        let outcome = self.run_frames(frames, completion);
+        self.gameboy.clear_debug_hooks();
+        outcome
+    }
+
+    fn run_frames(&mut self, frames: u64, completion: Option<&Completion>) -> Outcome {
+        while self.gameboy.frame_count() < frames {
+            let frame = self.gameboy.frame_count();
+            let stepped = self.gameboy.step_instruction().is_some();
+            let serial_received = !self.gameboy.cpu.mmu.serial.output.is_empty();
+            self.serial_output.append(&mut self.gameboy.cpu.mmu.serial.output);
+            if !stepped {
+                // Only the mooneye hook breaks
+                return self.mooneye_outcome();
+            }
+            // Events are not collected here, so only ones that are always reported, like a lockup, show up
+            if let Some(event) = self.gameboy.cpu.mmu.debug_events.take().first() {
+                return Outcome::Failed(event.describe());
+            }
+
+            match completion {
+                Some(Completion::Serial) if serial_received => {
+                    let output = self.serial_output();
+                    if output.contains("Passed") {
+                        return Outcome::Passed;
+                    } else if output.contains("Failed") {
+                        return Outcome::Failed("The test reported a failure over serial".to_string());
+                    }
+                }
+                Some(Completion::ScreenHash(hash))
+                    if self.gameboy.frame_count() != frame && self.screen_hash() == *hash =>
+                {
+                    return Outcome::Passed;
+                }
+                _ => {}
+            }
+        }
+
+        match completion {
+            None => Outcome::Passed,
+            Some(_) => Outcome::Failed(format!("No result after {} frames", frames)),
+        }
+    }
+
+    fn mooneye_outcome(&self) -> Outcome {
+        let registers = &self.gameboy.cpu.registers;
+        let values = [
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l,
+        ];
+        if values == MOONEYE_PASS {
+            Outcome::Passed
+        } else {
+            Outcome::Failed(format!(
+                "B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X}",
+                values[0], values[1], values[2], values[3], values[4], values[5]
+            ))
+        }
+    }
+
+    pub fn serial_output(&self) -> String {
+        String::from_utf8_lossy(&self.serial_output).into_owned()
+    }
+
+    /// FNV-1a hash of the RGB screen buffer.
+    pub fn screen_hash(&self) -> u64 {
+        self.gameboy
+            .framebuffer()
+            .iter()
+            .fold(0xCBF29CE484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001B3))
+    }
+
+    pub fn screenshot(&self) -> Screenshot {
+        Screenshot::from_screen_buffer(self.gameboy.framebuffer())
+    }
+
+    /// Compares the current frame against a reference PNG, writing a diff image to `diff_path` on mismatch.
+    pub fn compare_screen(&self, reference_path: &Path, diff_path: Option<&Path>) -> Result<Outcome> {
+        let expected = Screenshot::load_png(reference_path)?;
+        let Some((mismatches, diff)) = self.screenshot().diff(&expected) else {
+            return Ok(Outcome::Passed);
+        };
+        let mut reason = format!("{} pixels differ from {}", mismatches, reference_path.display());
+        if let Some(diff_path) = diff_path {
+            diff.save_png(diff_path)?;
+            reason += &format!(", diff written to {}", diff_path.display());
+        }
+        Ok(Outcome::Failed(reason))
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 #[inline(always)]
 fn bit(condition: bool) -> u8 {
     if condition {
@@ -32,6 +34,12 @@ pub struct Joypad {
     pub right: bool,
 }
 
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Joypad {
     pub fn new() -> Joypad {
         Joypad {
@@ -112,6 +120,66 @@ impl Joypad {
             Selected::None => 0xFF, // All bits set, meaning no buttons are pressed
         }
     }
+
+    /// Packs the pressed buttons into a byte, 1 means pressed.
+    /// Bits 0-3 are right, left, up and down, bits 4-7 are a, b, select and start.
+    pub fn get_buttons(&self) -> u8 {
+        bit(self.right)
+            | bit(self.left) << 1
+            | bit(self.up) << 2
+            | bit(self.down) << 3
+            | bit(self.a) << 4
+            | bit(self.b) << 5
+            | bit(self.select) << 6
+            | bit(self.start) << 7
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.right = is_set(buttons, 0);
+        self.left = is_set(buttons, 1);
+        self.up = is_set(buttons, 2);
+        self.down = is_set(buttons, 3);
+        self.a = is_set(buttons, 4);
+        self.b = is_set(buttons, 5);
+        self.select = is_set(buttons, 6);
+        self.start = is_set(buttons, 7);
+    }
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self.selected {
+            Selected::Buttons => 0,
+            Selected::DPad => 1,
+            Selected::Both => 2,
+            Selected::None => 3,
+        });
+        writer.write_bool(self.start);
+        writer.write_bool(self.select);
+        writer.write_bool(self.b);
+        writer.write_bool(self.a);
+        writer.write_bool(self.down);
+        writer.write_bool(self.up);
+        writer.write_bool(self.left);
+        writer.write_bool(self.right);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.selected = match reader.read_u8() {
+            0 => Selected::Buttons,
+            1 => Selected::DPad,
+            2 => Selected::Both,
+            _ => Selected::None,
+        };
+        self.start = reader.read_bool();
+        self.select = reader.read_bool();
+        self.b = reader.read_bool();
+        self.a = reader.read_bool();
+        self.down = reader.read_bool();
+        self.up = reader.read_bool();
+        self.left = reader.read_bool();
+        self.right = reader.read_bool();
+    }
 }
 
 #[cfg(test)]
@@ -233,4 +301,13 @@ mod test {
             };
         }
     }
+
+    #[test]
+    fn buttons_roundtrip() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(0b1001_0110);
+        assert!(joypad.start && joypad.a && joypad.up && joypad.left);
+        assert!(!joypad.select && !joypad.b && !joypad.down && !joypad.right);
+        assert_eq!(joypad.get_buttons(), 0b1001_0110);
+    }
 }
@@ -1,2 +1,3 @@
 pub mod joypad;
+pub mod serial;
 pub mod sound;
@@ -0,0 +1,114 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+// https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+// With the internal clock one bit is shifted every 512 cycles (8192 Hz)
+const CYCLES_PER_TRANSFER: u32 = 512 * 8;
+// Nothing drains `output` in the UI, so only the latest bytes are kept
+const MAX_OUTPUT: usize = 0x1000;
+
+/// Link port without a cable attached: bytes sent with the internal clock are collected in `output`
+/// and 0xFF is shifted in, transfers waiting for an external clock never complete.
+/// `output` is a log for test runners rather than machine state, savestates leave it alone.
+pub struct Serial {
+    data: u8,
+    control: u8,
+    transfer_cycles: u32,
+    pub output: Vec<u8>,
+    pub interrupt: u8,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            data: 0,
+            control: 0,
+            transfer_cycles: 0,
+            output: Vec::new(),
+            interrupt: 0,
+        }
+    }
+
+    pub fn read_byte(&self, a: u16) -> u8 {
+        match a {
+            0xFF01 => self.data,
+            0xFF02 => self.control | 0x7E,
+            _ => unreachable!("Serial does not handle read {:04X}", a),
+        }
+    }
+
+    pub fn write_byte(&mut self, a: u16, v: u8) {
+        match a {
+            0xFF01 => self.data = v,
+            0xFF02 => {
+                self.control = v & 0x81;
+                if self.control == 0x81 {
+                    // Transfer requested with the internal clock
+                    if self.output.len() == MAX_OUTPUT {
+                        self.output.remove(0);
+                    }
+                    self.output.push(self.data);
+                    self.transfer_cycles = CYCLES_PER_TRANSFER;
+                }
+            }
+            _ => unreachable!("Serial does not handle write {:04X}", a),
+        }
+    }
+
+    pub fn step(&mut self, ticks: u32) {
+        if self.transfer_cycles == 0 {
+            return;
+        }
+        self.transfer_cycles = self.transfer_cycles.saturating_sub(ticks);
+        if self.transfer_cycles == 0 {
+            self.data = 0xFF;
+            self.control &= 0x7F;
+            self.interrupt |= 0x08;
+        }
+    }
+}
+
+impl SaveState for Serial {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_u8(self.control);
+        writer.write_u32(self.transfer_cycles);
+        writer.write_u8(self.interrupt);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.data = reader.read_u8();
+        self.control = reader.read_u8();
+        self.transfer_cycles = reader.read_u32();
+        self.interrupt = reader.read_u8();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_keeps_the_latest_bytes_outside_savestates() {
+        let mut serial = Serial::new();
+        for byte in 0..=MAX_OUTPUT {
+            serial.write_byte(0xFF01, byte as u8);
+            serial.write_byte(0xFF02, 0x81);
+        }
+        assert_eq!(serial.output.len(), MAX_OUTPUT);
+        assert_eq!(serial.output[0], 1);
+
+        let mut writer = StateWriter::new();
+        serial.save_state(&mut writer);
+        let snapshot = writer.into_bytes();
+        assert!(snapshot.len() < 16);
+        serial.output.clear();
+        serial.load_state(&mut StateReader::new(&snapshot));
+        assert!(serial.output.is_empty());
+    }
+}
@@ -0,0 +1,1790 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use blip_buf::BlipBuf;
+
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::tracker::MusicLog;
+use crate::vgm::VgmWriter;
+use crate::wav::WavWriter;
+
+const WAVE_PATTERN: [[i32; 8]; 4] = [
+    [-1, -1, -1, -1, 1, -1, -1, -1],
+    [-1, -1, -1, -1, 1, 1, -1, -1],
+    [-1, -1, 1, 1, 1, 1, -1, -1],
+    [1, 1, 1, 1, -1, -1, 1, 1],
+];
+const CLOCKS_PER_SECOND: u32 = 1 << 22;
+const CLOCKS_PER_FRAME: u32 = CLOCKS_PER_SECOND / 512;
+const OUTPUT_SAMPLE_COUNT: usize = 2000; // this should be less than blip_buf::MAX_FRAME
+const SWEEP_DELAY_ZERO_PERIOD: u8 = 8;
+
+// Number of samples per channel kept for oscilloscope views
+const SCOPE_LENGTH: usize = 1024;
+
+// Additional delay on trigger of the wave channel (channel 3). In other emulators it is 6, but we
+// need 4 since we run the wave after delay == 0, instead of at delay == 0
+const WAVE_INITIAL_DELAY: u32 = 4;
+
+pub trait AudioPlayer: Send {
+    fn play(&mut self, left_channel: &[f32], right_channel: &[f32]);
+    fn samples_rate(&self) -> u32;
+    fn underflowed(&self) -> bool;
+    fn clear_buffer(&mut self);
+
+    /// Samples handed to `play` that the device has not consumed yet, None for players that do not
+    /// play in real time.
+    fn buffered_samples(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Discards all samples, for running without an audio device.
+pub struct NullPlayer {
+    sample_rate: u32,
+}
+
+impl Default for NullPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NullPlayer {
+    pub fn new() -> NullPlayer {
+        NullPlayer { sample_rate: 44100 }
+    }
+}
+
+impl AudioPlayer for NullPlayer {
+    fn play(&mut self, _buf_left: &[f32], _buf_right: &[f32]) {}
+
+    fn samples_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn underflowed(&self) -> bool {
+        false
+    }
+
+    fn clear_buffer(&mut self) {}
+}
+
+// Recordings keep the last 5 minutes, about 100 MB of samples at 44.1 kHz
+const MAX_RECORDING_SECONDS: u32 = 300;
+
+/// Keeps the samples it is given as (left, right) pairs, for inspecting the audio output without a device.
+/// Only the most recent ones are kept, up to a time limit. The buffer is shared, so it can still be read
+/// after the player has been handed to `Sound`.
+pub struct RecordingPlayer {
+    sample_rate: u32,
+    max_samples: usize,
+    samples: Arc<Mutex<VecDeque<(f32, f32)>>>,
+}
+
+impl RecordingPlayer {
+    pub fn new(sample_rate: u32) -> RecordingPlayer {
+        RecordingPlayer::with_limit(sample_rate, MAX_RECORDING_SECONDS)
+    }
+
+    /// Drops the oldest samples beyond `seconds` of audio.
+    pub fn with_limit(sample_rate: u32, seconds: u32) -> RecordingPlayer {
+        RecordingPlayer {
+            sample_rate,
+            max_samples: sample_rate as usize * seconds as usize,
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn samples(&self) -> Arc<Mutex<VecDeque<(f32, f32)>>> {
+        self.samples.clone()
+    }
+}
+
+impl AudioPlayer for RecordingPlayer {
+    fn play(&mut self, buf_left: &[f32], buf_right: &[f32]) {
+        debug_assert!(buf_left.len() == buf_right.len());
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(buf_left.iter().copied().zip(buf_right.iter().copied()));
+        let excess = samples.len().saturating_sub(self.max_samples);
+        samples.drain(..excess);
+    }
+
+    fn samples_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn underflowed(&self) -> bool {
+        false
+    }
+
+    fn clear_buffer(&mut self) {}
+}
+
+/// Decoded state of one channel, for debugger views. Fields a channel does not have are None.
+pub struct ChannelStatus {
+    pub active: bool,
+    pub dac_enabled: bool,
+    pub length_enabled: bool,
+    // Remaining length counter steps
+    pub length: u16,
+    // Current envelope volume 0-15, for the wave channel the output level (0 muted, 1 100%, 2 50%, 3 25%)
+    pub volume: u8,
+    pub envelope: Option<EnvelopeStatus>,
+    pub duty: Option<u8>,
+    pub sweep: Option<SweepStatus>,
+    // The 11 bit frequency register value of the square and wave channels
+    pub frequency: Option<u16>,
+    pub noise: Option<NoiseStatus>,
+    // Pitch of the output, the LFSR clock rate for the noise channel
+    pub frequency_hz: f32,
+}
+
+pub struct EnvelopeStatus {
+    pub initial_volume: u8,
+    pub goes_up: bool,
+    pub period: u8,
+}
+
+pub struct SweepStatus {
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+}
+
+pub struct NoiseStatus {
+    pub clock_shift: u8,
+    // 7 bit LFSR instead of 15 bit
+    pub short_mode: bool,
+    pub divisor_code: u8,
+}
+
+// Output frequency of a channel that advances one step every `period` clocks and repeats after `steps`
+fn period_to_hz(period: u32, steps: u32) -> f32 {
+    if period == 0 {
+        0.0
+    } else {
+        CLOCKS_PER_SECOND as f32 / (period * steps) as f32
+    }
+}
+
+/// The MIDI note number for a frequency, fractional when between notes. A4 (440 Hz) is 69.
+pub fn midi_note(hz: f32) -> f32 {
+    69.0 + 12.0 * (hz / 440.0).log2()
+}
+
+/// Name of a MIDI note number, like "C4" or "F#5".
+pub fn note_name(note: i32) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[note.rem_euclid(12) as usize], note.div_euclid(12) - 1)
+}
+
+struct VolumeEnvelope {
+    period: u8,
+    goes_up: bool,
+    delay: u8,
+    initial_volume: u8,
+    volume: u8,
+}
+
+impl VolumeEnvelope {
+    fn new() -> VolumeEnvelope {
+        VolumeEnvelope {
+            period: 0,
+            goes_up: false,
+            delay: 0,
+            initial_volume: 0,
+            volume: 0,
+        }
+    }
+
+    fn status(&self) -> EnvelopeStatus {
+        EnvelopeStatus {
+            initial_volume: self.initial_volume,
+            goes_up: self.goes_up,
+            period: self.period,
+        }
+    }
+
+    fn rb(&self, a: u16) -> u8 {
+        match a {
+            0xFF12 | 0xFF17 | 0xFF21 => {
+                ((self.initial_volume & 0xF) << 4)
+                    | if self.goes_up { 0x08 } else { 0 }
+                    | (self.period & 0x7)
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn wb(&mut self, a: u16, v: u8) {
+        match a {
+            0xFF12 | 0xFF17 | 0xFF21 => {
+                self.period = v & 0x7;
+                self.goes_up = v & 0x8 == 0x8;
+                self.initial_volume = v >> 4;
+                self.volume = self.initial_volume;
+            }
+            0xFF14 | 0xFF19 | 0xFF23 if v & 0x80 == 0x80 => {
+                self.delay = self.period;
+                self.volume = self.initial_volume;
+            }
+            _ => (),
+        }
+    }
+
+    fn step(&mut self) {
+        if self.delay > 1 {
+            self.delay -= 1;
+        } else if self.delay == 1 {
+            self.delay = self.period;
+            if self.goes_up && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.goes_up && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+struct LengthCounter {
+    enabled: bool,
+    value: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        LengthCounter {
+            enabled: false,
+            value: 0,
+            max,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.value > 0
+    }
+
+    fn extra_step(frame_step: u8) -> bool {
+        // if the last 'step' was a length step return true
+        // this is equivalent to (next) frame_step is not a length step
+        // which is true for odd steps
+        frame_step % 2 == 1
+    }
+
+    fn enable(&mut self, enable: bool, frame_step: u8) {
+        let was_enabled = self.enabled;
+        self.enabled = enable; // self.step takes the new value of enabled into account
+        if !was_enabled && LengthCounter::extra_step(frame_step) {
+            self.step();
+        }
+    }
+
+    fn set(&mut self, minus_value: u8) {
+        self.value = self.max - minus_value as u16;
+    }
+
+    fn trigger(&mut self, frame_step: u8) {
+        if self.value == 0 {
+            self.value = self.max;
+            if LengthCounter::extra_step(frame_step) {
+                self.step();
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        if self.enabled && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+}
+
+struct SquareChannel {
+    active: bool,
+    dac_enabled: bool,
+    duty: u8,
+    phase: u8,
+    length: LengthCounter,
+    frequency: u16,
+    period: u32,
+    last_amp: i32,
+    delay: u32,
+    has_sweep: bool,
+    sweep_enabled: bool,
+    sweep_frequency: u16,
+    sweep_delay: u8,
+    sweep_period: u8,
+    sweep_shift: u8,
+    sweep_negate: bool,
+    sweep_did_negate: bool,
+    volume_envelope: VolumeEnvelope,
+    blip: BlipBuf,
+}
+
+impl SquareChannel {
+    fn new(blip: BlipBuf, with_sweep: bool) -> SquareChannel {
+        SquareChannel {
+            active: false,
+            dac_enabled: false,
+            duty: 1,
+            phase: 1,
+            length: LengthCounter::new(64),
+            frequency: 0,
+            period: 2048,
+            last_amp: 0,
+            delay: 0,
+            has_sweep: with_sweep,
+            sweep_enabled: false,
+            sweep_frequency: 0,
+            sweep_delay: 0,
+            sweep_period: 0,
+            sweep_shift: 0,
+            sweep_negate: false,
+            sweep_did_negate: false,
+            volume_envelope: VolumeEnvelope::new(),
+            blip: blip,
+        }
+    }
+
+    fn on(&self) -> bool {
+        self.active
+    }
+
+    // Current 4-bit DAC input, as read through PCM12/PCM34
+    fn digital_output(&self) -> u8 {
+        self.last_amp.max(0) as u8
+    }
+
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            active: self.active,
+            dac_enabled: self.dac_enabled,
+            length_enabled: self.length.enabled,
+            length: self.length.value,
+            volume: self.volume_envelope.volume,
+            envelope: Some(self.volume_envelope.status()),
+            duty: Some(self.duty),
+            sweep: self.has_sweep.then_some(SweepStatus {
+                period: self.sweep_period,
+                negate: self.sweep_negate,
+                shift: self.sweep_shift,
+            }),
+            frequency: Some(self.frequency),
+            noise: None,
+            frequency_hz: period_to_hz(self.period, 8),
+        }
+    }
+
+    fn rb(&self, a: u16) -> u8 {
+        match a {
+            0xFF10 => {
+                0x80 | ((self.sweep_period & 0x7) << 4)
+                    | if self.sweep_negate { 0x8 } else { 0 }
+                    | (self.sweep_shift & 0x7)
+            }
+            0xFF11 | 0xFF16 => ((self.duty & 3) << 6) | 0x3F,
+            0xFF12 | 0xFF17 => self.volume_envelope.rb(a),
+            0xFF13 | 0xFF18 => 0xFF,
+            0xFF14 | 0xFF19 => 0x80 | if self.length.enabled { 0x40 } else { 0 } | 0x3F,
+            _ => unimplemented!(),
+        }
+    }
+
+    fn wb(&mut self, a: u16, v: u8, frame_step: u8) {
+        match a {
+            0xFF10 => {
+                self.sweep_period = (v >> 4) & 0x7;
+                self.sweep_shift = v & 0x7;
+                let old_sweep_negate = self.sweep_negate;
+                self.sweep_negate = v & 0x8 == 0x8;
+                if old_sweep_negate && !self.sweep_negate && self.sweep_did_negate {
+                    self.active = false;
+                }
+                self.sweep_did_negate = false;
+            }
+            0xFF11 | 0xFF16 => {
+                self.duty = v >> 6;
+                self.length.set(v & 0x3F);
+            }
+            0xFF12 | 0xFF17 => {
+                self.dac_enabled = v & 0xF8 != 0;
+                self.active = self.active && self.dac_enabled;
+            }
+            0xFF13 | 0xFF18 => {
+                self.frequency = (self.frequency & 0x0700) | (v as u16);
+                self.calculate_period();
+            }
+            0xFF14 | 0xFF19 => {
+                self.frequency = (self.frequency & 0x00FF) | (((v & 0b0000_0111) as u16) << 8);
+                self.calculate_period();
+
+                self.length.enable(v & 0x40 == 0x40, frame_step);
+                self.active &= self.length.is_active();
+
+                if v & 0x80 == 0x80 {
+                    if self.dac_enabled {
+                        self.active = true;
+                    }
+
+                    self.length.trigger(frame_step);
+
+                    if self.has_sweep {
+                        self.sweep_frequency = self.frequency;
+                        self.sweep_delay = if self.sweep_period != 0 {
+                            self.sweep_period
+                        } else {
+                            SWEEP_DELAY_ZERO_PERIOD
+                        };
+
+                        self.sweep_enabled = self.sweep_period > 0 || self.sweep_shift > 0;
+                        if self.sweep_shift > 0 {
+                            self.sweep_calculate_frequency();
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+        self.volume_envelope.wb(a, v);
+    }
+
+    fn calculate_period(&mut self) {
+        if self.frequency > 2047 {
+            self.period = 0;
+        } else {
+            self.period = (2048 - self.frequency as u32) * 4;
+        }
+    }
+
+    // This assumes no volume or sweep adjustments need to be done in the meantime
+    fn run(&mut self, start_time: u32, end_time: u32) {
+        if !self.active || self.period == 0 {
+            if self.last_amp != 0 {
+                self.blip.add_delta(start_time, -self.last_amp);
+                self.last_amp = 0;
+                self.delay = 0;
+            }
+        } else {
+            let mut time = start_time + self.delay;
+            let pattern = WAVE_PATTERN[self.duty as usize];
+            let vol = self.volume_envelope.volume as i32;
+
+            while time < end_time {
+                let amp = vol * pattern[self.phase as usize];
+                if amp != self.last_amp {
+                    self.blip.add_delta(time, amp - self.last_amp);
+                    self.last_amp = amp;
+                }
+                time += self.period;
+                self.phase = (self.phase + 1) % 8;
+            }
+
+            // next time, we have to wait an additional delay timesteps
+            self.delay = time - end_time;
+        }
+    }
+
+    fn step_length(&mut self) {
+        self.length.step();
+        self.active &= self.length.is_active();
+    }
+
+    fn sweep_calculate_frequency(&mut self) -> u16 {
+        let offset = self.sweep_frequency >> self.sweep_shift;
+
+        let newfreq = if self.sweep_negate {
+            self.sweep_did_negate = true;
+            self.sweep_frequency.wrapping_sub(offset)
+        } else {
+            self.sweep_frequency.wrapping_add(offset)
+        };
+
+        if newfreq > 2047 {
+            self.active = false;
+        }
+        return newfreq;
+    }
+
+    fn step_sweep(&mut self) {
+        debug_assert!(self.has_sweep);
+
+        if self.sweep_delay > 1 {
+            self.sweep_delay -= 1;
+        } else {
+            if self.sweep_period == 0 {
+                self.sweep_delay = SWEEP_DELAY_ZERO_PERIOD;
+            } else {
+                self.sweep_delay = self.sweep_period;
+                if self.sweep_enabled {
+                    let newfreq = self.sweep_calculate_frequency();
+                    if newfreq <= 2047 {
+                        if self.sweep_shift != 0 {
+                            self.sweep_frequency = newfreq;
+                            self.frequency = newfreq;
+                            self.calculate_period();
+                        }
+                        self.sweep_calculate_frequency();
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct WaveChannel {
+    active: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    frequency: u16,
+    period: u32,
+    last_amp: i32,
+    delay: u32,
+    volume_shift: u8,
+    waveram: [u8; 16],
+    current_wave: u8,
+    dmg_mode: bool,
+    sample_recently_accessed: bool,
+    blip: BlipBuf,
+}
+
+impl WaveChannel {
+    fn new(blip: BlipBuf, dmg_mode: bool) -> WaveChannel {
+        WaveChannel {
+            active: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            frequency: 0,
+            period: 2048,
+            last_amp: 0,
+            delay: 0,
+            volume_shift: 0,
+            waveram: [0; 16],
+            current_wave: 0,
+            dmg_mode: dmg_mode,
+            sample_recently_accessed: false,
+            blip: blip,
+        }
+    }
+
+    fn rb(&self, a: u16) -> u8 {
+        match a {
+            0xFF1A => (if self.dac_enabled { 0x80 } else { 0 }) | 0x7F,
+            0xFF1B => 0xFF,
+            0xFF1C => 0x80 | ((self.volume_shift & 0b11) << 5) | 0x1F,
+            0xFF1D => 0xFF,
+            0xFF1E => 0x80 | if self.length.enabled { 0x40 } else { 0 } | 0x3F,
+            0xFF30..=0xFF3F => {
+                if !self.active {
+                    self.waveram[a as usize - 0xFF30]
+                } else {
+                    if !self.dmg_mode || self.sample_recently_accessed {
+                        self.waveram[self.current_wave as usize >> 1]
+                    } else {
+                        0xFF
+                    }
+                }
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn wb(&mut self, a: u16, v: u8, frame_step: u8) {
+        match a {
+            0xFF1A => {
+                self.dac_enabled = (v & 0x80) == 0x80;
+                self.active = self.active && self.dac_enabled;
+            }
+            0xFF1B => self.length.set(v),
+            0xFF1C => self.volume_shift = (v >> 5) & 0b11,
+            0xFF1D => {
+                self.frequency = (self.frequency & 0x0700) | (v as u16);
+                self.calculate_period();
+            }
+            0xFF1E => {
+                self.frequency = (self.frequency & 0x00FF) | (((v & 0b111) as u16) << 8);
+                self.calculate_period();
+
+                self.length.enable(v & 0x40 == 0x40, frame_step);
+                self.active &= self.length.is_active();
+
+                if v & 0x80 == 0x80 {
+                    self.dmg_maybe_corrupt_waveram();
+
+                    self.length.trigger(frame_step);
+
+                    self.current_wave = 0;
+                    self.delay = self.period + WAVE_INITIAL_DELAY;
+
+                    if self.dac_enabled {
+                        self.active = true;
+                    }
+                }
+            }
+            0xFF30..=0xFF3F => {
+                if !self.active {
+                    self.waveram[a as usize - 0xFF30] = v;
+                } else {
+                    if !self.dmg_mode || self.sample_recently_accessed {
+                        self.waveram[self.current_wave as usize >> 1] = v;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn calculate_period(&mut self) {
+        if self.frequency > 2048 {
+            self.period = 0;
+        } else {
+            self.period = (2048 - self.frequency as u32) * 2;
+        }
+    }
+
+    fn on(&self) -> bool {
+        self.active
+    }
+
+    // Current 4-bit DAC input after the output level shift, as read through PCM34
+    fn digital_output(&self) -> u8 {
+        // last_amp is kept at 4x the sample value
+        (self.last_amp >> 2) as u8
+    }
+
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            active: self.active,
+            dac_enabled: self.dac_enabled,
+            length_enabled: self.length.enabled,
+            length: self.length.value,
+            volume: self.volume_shift,
+            envelope: None,
+            duty: None,
+            sweep: None,
+            frequency: Some(self.frequency),
+            noise: None,
+            frequency_hz: period_to_hz(self.period, 32),
+        }
+    }
+
+    fn run(&mut self, start_time: u32, end_time: u32) {
+        self.sample_recently_accessed = false;
+        if !self.active || self.period == 0 {
+            if self.last_amp != 0 {
+                self.blip.add_delta(start_time, -self.last_amp);
+                self.last_amp = 0;
+                self.delay = 0;
+            }
+        } else {
+            let mut time = start_time + self.delay;
+
+            // A sample may be muted, 100%, 50% or 25%.
+            // To avoid loss of precision, the WaveChannel will output
+            // i32 samples at 4x the usual amplitude. This will be taken
+            // into account when mixing all the samples.
+            let volshift = match self.volume_shift {
+                0 => 4 + 2, // to mute a 4 bit sample mutiplied by 2^2
+                1 => 0,
+                2 => 1,
+                3 => 2,
+                _ => unreachable!(),
+            };
+
+            while time < end_time {
+                let wavebyte = self.waveram[self.current_wave as usize >> 1];
+                let sample = if self.current_wave % 2 == 0 {
+                    wavebyte >> 4
+                } else {
+                    wavebyte & 0xF
+                };
+
+                // shifted by 2 so that 25% does not lose precision
+                let amp = ((sample << 2) >> volshift) as i32;
+
+                if amp != self.last_amp {
+                    self.blip.add_delta(time, amp - self.last_amp);
+                    self.last_amp = amp;
+                }
+
+                if time >= end_time - 2 {
+                    // Mark the wave sample as recently accessed.
+                    // The DMG only allows a wave sample to be read at this point in time.
+                    self.sample_recently_accessed = true;
+                }
+                time += self.period;
+                self.current_wave = (self.current_wave + 1) % 32;
+            }
+
+            // next time, we have to wait an additional delay timesteps
+            self.delay = time - end_time;
+        }
+    }
+
+    fn step_length(&mut self) {
+        self.length.step();
+        self.active &= self.length.is_active();
+    }
+
+    fn dmg_maybe_corrupt_waveram(&mut self) {
+        // Corrupt when in dmg_mode, the channel is active, and
+        // when delay == 0. Delay == 0 means that the next cycle will
+        // run the sample, and thus the corruption occurs.
+        // Since the corruption occurs after this code, we need to
+        // increase current_wave by one as well.
+        if !self.dmg_mode || !self.active || self.delay != 0 {
+            return;
+        }
+
+        let byteindex = ((self.current_wave + 1) % 32) as usize >> 1;
+
+        if byteindex < 4 {
+            self.waveram[0] = self.waveram[byteindex];
+        } else {
+            let blockstart = byteindex & 0b1100;
+            self.waveram[0] = self.waveram[blockstart];
+            self.waveram[1] = self.waveram[blockstart + 1];
+            self.waveram[2] = self.waveram[blockstart + 2];
+            self.waveram[3] = self.waveram[blockstart + 3];
+        }
+    }
+}
+
+struct NoiseChannel {
+    active: bool,
+    dac_enabled: bool,
+    reg_ff22: u8,
+    length: LengthCounter,
+    volume_envelope: VolumeEnvelope,
+    period: u32,
+    shift_width: u8,
+    state: u16,
+    delay: u32,
+    last_amp: i32,
+    blip: BlipBuf,
+}
+
+impl NoiseChannel {
+    fn new(blip: BlipBuf) -> NoiseChannel {
+        NoiseChannel {
+            active: false,
+            dac_enabled: false,
+            reg_ff22: 0,
+            length: LengthCounter::new(64),
+            volume_envelope: VolumeEnvelope::new(),
+            period: 2048,
+            shift_width: 14,
+            state: 1,
+            delay: 0,
+            last_amp: 0,
+            blip: blip,
+        }
+    }
+
+    fn rb(&self, a: u16) -> u8 {
+        match a {
+            0xFF20 => 0xFF,
+            0xFF21 => self.volume_envelope.rb(a),
+            0xFF22 => self.reg_ff22,
+            0xFF23 => 0x80 | if self.length.enabled { 0x40 } else { 0 } | 0x3F,
+            _ => unimplemented!(),
+        }
+    }
+
+    fn wb(&mut self, a: u16, v: u8, frame_step: u8) {
+        match a {
+            0xFF20 => self.length.set(v & 0x3F),
+            0xFF21 => {
+                self.dac_enabled = v & 0xF8 != 0;
+                self.active = self.active && self.dac_enabled;
+            }
+            0xFF22 => {
+                self.reg_ff22 = v;
+                self.shift_width = if v & 8 == 8 { 6 } else { 14 };
+                let freq_div = match v & 7 {
+                    0 => 8,
+                    n => n as u32 * 16,
+                };
+                self.period = freq_div << (v >> 4);
+            }
+            0xFF23 => {
+                self.length.enable(v & 0x40 == 0x40, frame_step);
+                self.active &= self.length.is_active();
+
+                if v & 0x80 == 0x80 {
+                    self.length.trigger(frame_step);
+
+                    self.state = 0xFF;
+                    self.delay = 0;
+
+                    if self.dac_enabled {
+                        self.active = true;
+                    }
+                }
+            }
+            _ => (),
+        }
+        self.volume_envelope.wb(a, v);
+    }
+
+    fn on(&self) -> bool {
+        self.active
+    }
+
+    // Current 4-bit DAC input, as read through PCM12/PCM34
+    fn digital_output(&self) -> u8 {
+        self.last_amp.max(0) as u8
+    }
+
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            active: self.active,
+            dac_enabled: self.dac_enabled,
+            length_enabled: self.length.enabled,
+            length: self.length.value,
+            volume: self.volume_envelope.volume,
+            envelope: Some(self.volume_envelope.status()),
+            duty: None,
+            sweep: None,
+            frequency: None,
+            noise: Some(NoiseStatus {
+                clock_shift: self.reg_ff22 >> 4,
+                short_mode: self.reg_ff22 & 8 == 8,
+                divisor_code: self.reg_ff22 & 7,
+            }),
+            frequency_hz: period_to_hz(self.period, 1),
+        }
+    }
+
+    fn run(&mut self, start_time: u32, end_time: u32) {
+        if !self.active {
+            if self.last_amp != 0 {
+                self.blip.add_delta(start_time, -self.last_amp);
+                self.last_amp = 0;
+                self.delay = 0;
+            }
+        } else {
+            let mut time = start_time + self.delay;
+            while time < end_time {
+                let oldstate = self.state;
+                self.state <<= 1;
+                let bit = ((oldstate >> self.shift_width) ^ (self.state >> self.shift_width)) & 1;
+                self.state |= bit;
+
+                let amp = match (oldstate >> self.shift_width) & 1 {
+                    0 => -(self.volume_envelope.volume as i32),
+                    _ => self.volume_envelope.volume as i32,
+                };
+
+                if self.last_amp != amp {
+                    self.blip.add_delta(time, amp - self.last_amp);
+                    self.last_amp = amp;
+                }
+
+                time += self.period;
+            }
+            self.delay = time - end_time;
+        }
+    }
+
+    fn step_length(&mut self) {
+        self.length.step();
+        self.active &= self.length.is_active();
+    }
+}
+
+pub struct Sound {
+    on: bool,
+    time: u32,
+    prev_time: u32,
+    next_time: u32,
+    frame_step: u8,
+    output_period: u32,
+    channel1: SquareChannel,
+    channel2: SquareChannel,
+    channel3: WaveChannel,
+    channel4: NoiseChannel,
+    volume_left: u8,
+    volume_right: u8,
+    reg_vin_to_so: u8,
+    reg_ff25: u8,
+    need_sync: bool,
+    dmg_mode: bool,
+    player: Box<dyn AudioPlayer>,
+    wav: Option<WavWriter>,
+    // One stereo WAV per channel, written before muting
+    stems: Option<[WavWriter; 4]>,
+    // Indexed by channel number - 1, a muted channel is left out of the mix
+    pub muted: [bool; 4],
+    // When any channel is soloed only the soloed channels are mixed
+    pub soloed: [bool; 4],
+    // Ring buffers of the latest output of every channel, before panning and volume
+    scope: [Vec<f32>; 4],
+    scope_position: usize,
+    // Cycles since power on, including while the APU is off
+    clock: u64,
+    // Channel changes for the tracker view, only collected while Some
+    pub music_log: Option<MusicLog>,
+    vgm: Option<VgmWriter>,
+    // Output rate multiplier for dynamic rate control, 1.0 plays at the player's sample rate
+    rate_adjustment: f64,
+    // Last value accepted by every register FF10-FF3F, to start VGM logs from the current state
+    written: [u8; 0x30],
+    // Why a recording stopped on its own, until the front end picks it up
+    recording_error: Option<anyhow::Error>,
+}
+
+impl Sound {
+    pub fn new_dmg(player: Box<dyn AudioPlayer>) -> Sound {
+        Sound::new_internal(player, true)
+    }
+
+    pub fn new_cgb(player: Box<dyn AudioPlayer>) -> Sound {
+        Sound::new_internal(player, false)
+    }
+
+    fn new_internal(player: Box<dyn AudioPlayer>, dmg_mode: bool) -> Sound {
+        let blipbuf1 = create_blipbuf(player.samples_rate());
+        let blipbuf2 = create_blipbuf(player.samples_rate());
+        let blipbuf3 = create_blipbuf(player.samples_rate());
+        let blipbuf4 = create_blipbuf(player.samples_rate());
+
+        let output_period =
+            (OUTPUT_SAMPLE_COUNT as u64 * CLOCKS_PER_SECOND as u64) / player.samples_rate() as u64;
+
+        Sound {
+            on: false,
+            time: 0,
+            prev_time: 0,
+            next_time: CLOCKS_PER_FRAME,
+            frame_step: 0,
+            output_period: output_period as u32,
+            channel1: SquareChannel::new(blipbuf1, true),
+            channel2: SquareChannel::new(blipbuf2, false),
+            channel3: WaveChannel::new(blipbuf3, dmg_mode),
+            channel4: NoiseChannel::new(blipbuf4),
+            volume_left: 7,
+            volume_right: 7,
+            reg_vin_to_so: 0x00,
+            reg_ff25: 0x00,
+            need_sync: false,
+            dmg_mode,
+            player,
+            wav: None,
+            stems: None,
+            muted: [false; 4],
+            soloed: [false; 4],
+            scope: std::array::from_fn(|_| vec![0.0; SCOPE_LENGTH]),
+            scope_position: 0,
+            clock: 0,
+            music_log: None,
+            vgm: None,
+            rate_adjustment: 1.0,
+            written: [0; 0x30],
+            recording_error: None,
+        }
+    }
+
+    pub fn player(&self) -> &dyn AudioPlayer {
+        self.player.as_ref()
+    }
+
+    pub fn player_mut(&mut self) -> &mut dyn AudioPlayer {
+        self.player.as_mut()
+    }
+
+    /// Switches to another player, samples not yet handed to the old player are dropped.
+    /// A WAV recording is finished since the sample rate may change.
+    pub fn set_player(&mut self, player: Box<dyn AudioPlayer>) {
+        if let Err(e) = self.stop_wav_recording().and_then(|_| self.stop_stem_recording()) {
+            eprintln!("Could not finish WAV recording: {}", e);
+        }
+        self.clear_buffers();
+        self.player = player;
+        self.apply_rates();
+    }
+
+    /// Resamples the output to `ratio` times the player's sample rate, which changes how fast the
+    /// player's buffer fills without changing the emulation speed. Used to keep the latency on target.
+    /// Ignored while a WAV or stem recording runs, recordings stay at the nominal rate.
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        if ratio != self.rate_adjustment {
+            self.rate_adjustment = ratio;
+            self.apply_rates();
+        }
+    }
+
+    /// The output rate multiplier in effect, 1.0 while recording.
+    pub fn rate_adjustment(&self) -> f64 {
+        if self.wav.is_some() || self.stems.is_some() {
+            1.0
+        } else {
+            self.rate_adjustment
+        }
+    }
+
+    /// Whether the APU is powered (NR52 bit 7), no samples are produced while it is off.
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Samples produced since the last batch handed to the player, they reach it with the next batch.
+    pub fn unmixed_samples(&self) -> usize {
+        (self.time as f64 * self.output_rate() / CLOCKS_PER_SECOND as f64) as usize
+    }
+
+    fn output_rate(&self) -> f64 {
+        self.player.samples_rate() as f64 * self.rate_adjustment()
+    }
+
+    fn apply_rates(&mut self) {
+        let output_rate = self.output_rate();
+        for blip in [
+            &mut self.channel1.blip,
+            &mut self.channel2.blip,
+            &mut self.channel3.blip,
+            &mut self.channel4.blip,
+        ] {
+            blip.set_rates(CLOCKS_PER_SECOND as f64, output_rate);
+        }
+        // Batches stay at OUTPUT_SAMPLE_COUNT samples so they fit the blip buffers
+        self.output_period = (OUTPUT_SAMPLE_COUNT as f64 * CLOCKS_PER_SECOND as f64 / output_rate) as u32;
+    }
+
+    /// Starts writing the mixed output to a stereo WAV file at the player's sample rate.
+    pub fn start_wav_recording(&mut self, path: &Path) -> Result<()> {
+        self.stop_wav_recording()?;
+        self.wav = Some(WavWriter::create(path, 2, self.player.samples_rate())?);
+        self.apply_rates();
+        Ok(())
+    }
+
+    pub fn stop_wav_recording(&mut self) -> Result<()> {
+        match self.wav.take() {
+            Some(wav) => {
+                self.apply_rates();
+                wav.finish().map(|_| ())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording_wav(&self) -> bool {
+        self.wav.is_some()
+    }
+
+    /// Starts writing every channel to its own stereo WAV, see `stem_path` for the file names.
+    /// Stems include muted channels.
+    pub fn start_stem_recording(&mut self, path: &Path) -> Result<()> {
+        self.stop_stem_recording()?;
+        let samples_rate = self.player.samples_rate();
+        self.stems = Some([
+            WavWriter::create(&stem_path(path, 0), 2, samples_rate)?,
+            WavWriter::create(&stem_path(path, 1), 2, samples_rate)?,
+            WavWriter::create(&stem_path(path, 2), 2, samples_rate)?,
+            WavWriter::create(&stem_path(path, 3), 2, samples_rate)?,
+        ]);
+        self.apply_rates();
+        Ok(())
+    }
+
+    pub fn stop_stem_recording(&mut self) -> Result<()> {
+        let Some(stems) = self.stems.take() else {
+            return Ok(());
+        };
+        self.apply_rates();
+        let mut result = Ok(());
+        for stem in stems {
+            // Finish every file even when one fails, reporting the first error
+            let finished = stem.finish();
+            if result.is_ok() {
+                result = finished.map(|_| ());
+            }
+        }
+        result
+    }
+
+    pub fn is_recording_stems(&self) -> bool {
+        self.stems.is_some()
+    }
+
+    /// Starts logging every register write to a VGM file. The log begins with the registers as they are
+    /// now, channels that are already playing are heard from their next trigger.
+    pub fn start_vgm_recording(&mut self, path: &Path) -> Result<()> {
+        self.stop_vgm_recording()?;
+        let mut vgm = VgmWriter::create(path, self.clock)?;
+        for (address, value) in self.register_snapshot() {
+            vgm.write_register(self.clock, address, value)?;
+        }
+        self.vgm = Some(vgm);
+        Ok(())
+    }
+
+    pub fn stop_vgm_recording(&mut self) -> Result<()> {
+        match self.vgm.take() {
+            Some(vgm) => vgm.finish(self.clock).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording_vgm(&self) -> bool {
+        self.vgm.is_some()
+    }
+
+    /// Returns why a WAV, stem or VGM recording stopped by itself after a write failed.
+    /// What was written up to then is finished and can be played.
+    pub fn take_recording_error(&mut self) -> Option<anyhow::Error> {
+        self.recording_error.take()
+    }
+
+    // The failed write is the error worth reporting, finishing is a best effort to keep the file playable
+    fn recording_failed(&mut self, kind: &str, error: anyhow::Error, finished: Result<()>) {
+        if let Err(e) = finished {
+            eprintln!("Could not finish {} recording: {}", kind, e);
+        }
+        self.recording_error = Some(error.context(format!("{} recording stopped", kind)));
+    }
+
+    // Writes that bring a freshly powered on APU into the current state, without triggering channels
+    fn register_snapshot(&self) -> Vec<(u16, u8)> {
+        let written = |address: u16| self.written[address as usize - 0xFF10];
+        let mut writes = vec![(0xFF26, if self.on { 0x80 } else { 0x00 })];
+        if !self.on {
+            return writes;
+        }
+        writes.push((0xFF24, written(0xFF24)));
+        writes.push((0xFF25, written(0xFF25)));
+        // Wave RAM is only fully accessible with the wave channel off
+        writes.push((0xFF1A, 0x00));
+        for (index, &byte) in self.channel3.waveram.iter().enumerate() {
+            writes.push((0xFF30 + index as u16, byte));
+        }
+        for address in (0xFF10..=0xFF23).filter(|address| !matches!(address, 0xFF15 | 0xFF1F)) {
+            let value = match address {
+                0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => written(address) & 0x7F,
+                _ => written(address),
+            };
+            writes.push((address, value));
+        }
+        writes
+    }
+
+    /// Whether `channel` (0 to 3) is part of the mix, taking mute and solo into account.
+    pub fn channel_audible(&self, channel: usize) -> bool {
+        if self.soloed.iter().any(|&soloed| soloed) {
+            self.soloed[channel]
+        } else {
+            !self.muted[channel]
+        }
+    }
+
+    /// Decoded state of `channel` (0 to 3).
+    pub fn channel_status(&self, channel: usize) -> ChannelStatus {
+        match channel {
+            0 => self.channel1.status(),
+            1 => self.channel2.status(),
+            2 => self.channel3.status(),
+            _ => self.channel4.status(),
+        }
+    }
+
+    /// The 4-bit digital output of `channel` (0 to 3) as of the last time the APU caught up.
+    pub fn digital_output(&self, channel: usize) -> u8 {
+        match channel {
+            0 => self.channel1.digital_output(),
+            1 => self.channel2.digital_output(),
+            2 => self.channel3.digital_output(),
+            _ => self.channel4.digital_output(),
+        }
+    }
+
+    /// PCM12 (FF76): the digital output of channel 2 in the upper and channel 1 in the lower nibble (CGB).
+    pub fn pcm12(&mut self) -> u8 {
+        self.run();
+        self.digital_output(1) << 4 | self.digital_output(0)
+    }
+
+    /// PCM34 (FF77): the digital output of channel 4 in the upper and channel 3 in the lower nibble (CGB).
+    pub fn pcm34(&mut self) -> u8 {
+        self.run();
+        self.digital_output(3) << 4 | self.digital_output(2)
+    }
+
+    /// The latest output samples of `channel` (0 to 3), oldest first, in the range -1.0..=1.0.
+    pub fn scope(&self, channel: usize) -> Vec<f32> {
+        let samples = &self.scope[channel];
+        samples[self.scope_position..]
+            .iter()
+            .chain(&samples[..self.scope_position])
+            .copied()
+            .collect()
+    }
+
+    /// Wave RAM (FF30-FF3F) as stored, without the access restrictions of a running wave channel.
+    pub fn wave_ram(&self) -> [u8; 16] {
+        self.channel3.waveram
+    }
+
+    /// Sets one of the 32 4-bit wave samples, for editing wave RAM from a debugger.
+    pub fn set_wave_sample(&mut self, index: usize, value: u8) {
+        let byte = &mut self.channel3.waveram[index / 2];
+        // Even samples are in the high nibble
+        *byte = match index % 2 {
+            0 => (*byte & 0x0F) | (value << 4),
+            _ => (*byte & 0xF0) | (value & 0x0F),
+        };
+    }
+
+    pub fn rb(&mut self, a: u16) -> u8 {
+        self.run();
+        let v = match a {
+            0xFF10..=0xFF14 => self.channel1.rb(a),
+            0xFF16..=0xFF19 => self.channel2.rb(a),
+            0xFF1A..=0xFF1E => self.channel3.rb(a),
+            0xFF20..=0xFF23 => self.channel4.rb(a),
+            0xFF24 => ((self.volume_right & 7) << 4) | (self.volume_left & 7) | self.reg_vin_to_so,
+            0xFF25 => self.reg_ff25,
+            0xFF26 => {
+                (if self.on { 0x80 } else { 0x00 }
+                    | 0x70
+                    | if self.channel4.on() { 0x8 } else { 0x0 }
+                    | if self.channel3.on() { 0x4 } else { 0x0 }
+                    | if self.channel2.on() { 0x2 } else { 0x0 }
+                    | if self.channel1.on() { 0x1 } else { 0x0 })
+            }
+            0xFF30..=0xFF3F => self.channel3.rb(a),
+            _ => 0xFF,
+        };
+        return v;
+    }
+
+    pub fn wb(&mut self, a: u16, v: u8) {
+        if let Some(Err(e)) = self.vgm.as_mut().map(|vgm| vgm.write_register(self.clock, a, v)) {
+            let finished = self.stop_vgm_recording();
+            self.recording_failed("VGM", e, finished);
+        }
+        if !self.on {
+            // Allow writes to the length register when in DMG mode
+            if self.dmg_mode {
+                match a {
+                    0xFF11 => self.channel1.wb(a, v & 0x3F, self.frame_step),
+                    0xFF16 => self.channel2.wb(a, v & 0x3F, self.frame_step),
+                    0xFF1B => self.channel3.wb(a, v, self.frame_step),
+                    0xFF20 => self.channel4.wb(a, v & 0x3F, self.frame_step),
+                    _ => (),
+                }
+            }
+            // Only allow further writes to the control register
+            if a != 0xFF26 {
+                return;
+            }
+        }
+        self.run();
+        self.written[a as usize - 0xFF10] = v;
+        match a {
+            0xFF10..=0xFF14 => self.channel1.wb(a, v, self.frame_step),
+            0xFF16..=0xFF19 => self.channel2.wb(a, v, self.frame_step),
+            0xFF1A..=0xFF1E => self.channel3.wb(a, v, self.frame_step),
+            0xFF20..=0xFF23 => self.channel4.wb(a, v, self.frame_step),
+            0xFF24 => {
+                self.volume_left = v & 0x7;
+                self.volume_right = (v >> 4) & 0x7;
+                self.reg_vin_to_so = v & 0x88;
+            }
+            0xFF25 => self.reg_ff25 = v,
+            0xFF26 => {
+                let turn_on = v & 0x80 == 0x80;
+                if self.on && turn_on == false {
+                    // Reset all registers to 0 when turning off, a VGM player does the same on its own
+                    let vgm = self.vgm.take();
+                    for i in 0xFF10..=0xFF25 {
+                        self.wb(i, 0);
+                    }
+                    self.vgm = vgm;
+                }
+                if !self.on && turn_on {
+                    // Reset frame step when turning on
+                    self.frame_step = 0;
+                }
+                self.on = turn_on;
+            }
+            0xFF30..=0xFF3F => self.channel3.wb(a, v, self.frame_step),
+            _ => (),
+        }
+
+        if self.music_log.is_some() {
+            self.log_write(a, v);
+        }
+    }
+
+    fn log_write(&mut self, a: u16, v: u8) {
+        let channel = match a {
+            0xFF10..=0xFF14 => 0,
+            0xFF16..=0xFF19 => 1,
+            0xFF1A..=0xFF1E => 2,
+            0xFF20..=0xFF23 => 3,
+            _ => return,
+        };
+        let triggered = matches!(a, 0xFF14 | 0xFF19 | 0xFF1E | 0xFF23) && v & 0x80 == 0x80;
+        let status = self.channel_status(channel);
+        if let Some(log) = &mut self.music_log {
+            log.record(self.clock, channel, &status, triggered);
+        }
+    }
+
+    /// Cycles since power on, the time base of `music_log`.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn do_cycle(&mut self, cycles: u32) {
+        self.clock += cycles as u64;
+        if !self.on {
+            return;
+        }
+
+        self.time += cycles;
+
+        if self.time >= self.output_period {
+            self.do_output();
+        }
+    }
+
+    pub fn sync(&mut self) {
+        self.need_sync = true;
+    }
+
+    fn do_output(&mut self) {
+        self.run();
+        debug_assert!(self.time == self.prev_time);
+        self.channel1.blip.end_frame(self.time);
+        self.channel2.blip.end_frame(self.time);
+        self.channel3.blip.end_frame(self.time);
+        self.channel4.blip.end_frame(self.time);
+        self.next_time -= self.time;
+        self.time = 0;
+        self.prev_time = 0;
+
+        if !self.need_sync || self.player.underflowed() {
+            self.need_sync = false;
+            self.mix_buffers();
+        } else {
+            // Prevent the BlipBuf's from filling up and triggering an assertion
+            self.clear_buffers();
+        }
+    }
+
+    fn run(&mut self) {
+        while self.next_time <= self.time {
+            self.channel1.run(self.prev_time, self.next_time);
+            self.channel2.run(self.prev_time, self.next_time);
+            self.channel3.run(self.prev_time, self.next_time);
+            self.channel4.run(self.prev_time, self.next_time);
+
+            if self.frame_step % 2 == 0 {
+                self.channel1.step_length();
+                self.channel2.step_length();
+                self.channel3.step_length();
+                self.channel4.step_length();
+            }
+            if self.frame_step % 4 == 2 {
+                self.channel1.step_sweep();
+            }
+            if self.frame_step == 7 {
+                self.channel1.volume_envelope.step();
+                self.channel2.volume_envelope.step();
+                self.channel4.volume_envelope.step();
+            }
+
+            self.frame_step = (self.frame_step + 1) % 8;
+
+            self.prev_time = self.next_time;
+            self.next_time += CLOCKS_PER_FRAME;
+        }
+
+        if self.prev_time != self.time {
+            self.channel1.run(self.prev_time, self.time);
+            self.channel2.run(self.prev_time, self.time);
+            self.channel3.run(self.prev_time, self.time);
+            self.channel4.run(self.prev_time, self.time);
+
+            self.prev_time = self.time;
+        }
+
+        if let Some(log) = &mut self.music_log {
+            let active = [self.channel1.on(), self.channel2.on(), self.channel3.on(), self.channel4.on()];
+            for (channel, active) in active.into_iter().enumerate() {
+                log.check_stopped(self.clock, channel, active);
+            }
+        }
+    }
+
+    fn mix_buffers(&mut self) {
+        let sample_count = self.channel1.blip.samples_avail() as usize;
+        debug_assert!(sample_count == self.channel2.blip.samples_avail() as usize);
+        debug_assert!(sample_count == self.channel3.blip.samples_avail() as usize);
+        debug_assert!(sample_count == self.channel4.blip.samples_avail() as usize);
+
+        let mut outputted = 0;
+
+        let left_vol = (self.volume_left as f32 / 7.0) * (1.0 / 15.0) * 0.25;
+        let right_vol = (self.volume_right as f32 / 7.0) * (1.0 / 15.0) * 0.25;
+
+        while outputted < sample_count {
+            let buf_left = &mut [0f32; OUTPUT_SAMPLE_COUNT + 10];
+            let buf_right = &mut [0f32; OUTPUT_SAMPLE_COUNT + 10];
+            let channel_left = &mut [0f32; OUTPUT_SAMPLE_COUNT + 10];
+            let channel_right = &mut [0f32; OUTPUT_SAMPLE_COUNT + 10];
+            let buf = &mut [0i16; OUTPUT_SAMPLE_COUNT + 10];
+
+            let mut counts = [0; 4];
+            for channel in 0..4 {
+                let blip = match channel {
+                    0 => &mut self.channel1.blip,
+                    1 => &mut self.channel2.blip,
+                    2 => &mut self.channel3.blip,
+                    _ => &mut self.channel4.blip,
+                };
+                let count = blip.read_samples(buf, false);
+                counts[channel] = count;
+
+                // channel3 is the WaveChannel, that outputs samples with a 4x
+                // increase in amplitude in order to avoid a loss of precision.
+                let scale = if channel == 2 { 0.25 } else { 1.0 };
+                let to_left = self.reg_ff25 & (0x10 << channel) != 0;
+                let to_right = self.reg_ff25 & (0x01 << channel) != 0;
+                let scope = &mut self.scope[channel];
+                for (i, v) in buf[..count].iter().enumerate() {
+                    let v = *v as f32 * scale;
+                    scope[(self.scope_position + i) % SCOPE_LENGTH] = v / 15.0;
+                    channel_left[i] = if to_left { v * left_vol } else { 0.0 };
+                    channel_right[i] = if to_right { v * right_vol } else { 0.0 };
+                }
+
+                if self.channel_audible(channel) {
+                    for i in 0..count {
+                        buf_left[i] += channel_left[i];
+                        buf_right[i] += channel_right[i];
+                    }
+                }
+                let written = self
+                    .stems
+                    .as_mut()
+                    .map(|stems| stems[channel].write_stereo(&channel_left[..count], &channel_right[..count]));
+                if let Some(Err(e)) = written {
+                    let finished = self.stop_stem_recording();
+                    self.recording_failed("Stem", e, finished);
+                }
+            }
+
+            let count1 = counts[0];
+            self.scope_position = (self.scope_position + count1) % SCOPE_LENGTH;
+            debug_assert!(count1 == counts[1]);
+            debug_assert!(count1 == counts[2]);
+            debug_assert!(count1 == counts[3]);
+
+            self.player.play(&buf_left[..count1], &buf_right[..count1]);
+            let written = self
+                .wav
+                .as_mut()
+                .map(|wav| wav.write_stereo(&buf_left[..count1], &buf_right[..count1]));
+            if let Some(Err(e)) = written {
+                let finished = self.stop_wav_recording();
+                self.recording_failed("WAV", e, finished);
+            }
+
+            outputted += count1;
+        }
+    }
+
+    fn clear_buffers(&mut self) {
+        self.channel1.blip.clear();
+        self.channel2.blip.clear();
+        self.channel3.blip.clear();
+        self.channel4.blip.clear();
+    }
+}
+
+impl Drop for Sound {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_wav_recording().and_then(|_| self.stop_stem_recording()) {
+            eprintln!("Could not finish WAV recording: {}", e);
+        }
+        if let Err(e) = self.stop_vgm_recording() {
+            eprintln!("Could not finish VGM recording: {}", e);
+        }
+    }
+}
+
+impl SaveState for VolumeEnvelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.period);
+        writer.write_bool(self.goes_up);
+        writer.write_u8(self.delay);
+        writer.write_u8(self.initial_volume);
+        writer.write_u8(self.volume);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.period = reader.read_u8();
+        self.goes_up = reader.read_bool();
+        self.delay = reader.read_u8();
+        self.initial_volume = reader.read_u8();
+        self.volume = reader.read_u8();
+    }
+}
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.value);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.enabled = reader.read_bool();
+        self.value = reader.read_u16();
+    }
+}
+
+// The BlipBufs are not part of the state, they are cleared on load instead. Because of that the
+// last amplitude of every channel is reset as well, so the next run starts from silence.
+impl SaveState for SquareChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.active);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.phase);
+        self.length.save_state(writer);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.period);
+        writer.write_u32(self.delay);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u16(self.sweep_frequency);
+        writer.write_u8(self.sweep_delay);
+        writer.write_u8(self.sweep_period);
+        writer.write_u8(self.sweep_shift);
+        writer.write_bool(self.sweep_negate);
+        writer.write_bool(self.sweep_did_negate);
+        self.volume_envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.active = reader.read_bool();
+        self.dac_enabled = reader.read_bool();
+        self.duty = reader.read_u8();
+        self.phase = reader.read_u8();
+        self.length.load_state(reader);
+        self.frequency = reader.read_u16();
+        self.period = reader.read_u32();
+        self.delay = reader.read_u32();
+        self.sweep_enabled = reader.read_bool();
+        self.sweep_frequency = reader.read_u16();
+        self.sweep_delay = reader.read_u8();
+        self.sweep_period = reader.read_u8();
+        self.sweep_shift = reader.read_u8();
+        self.sweep_negate = reader.read_bool();
+        self.sweep_did_negate = reader.read_bool();
+        self.volume_envelope.load_state(reader);
+        self.last_amp = 0;
+        self.blip.clear();
+    }
+}
+
+impl SaveState for WaveChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.active);
+        writer.write_bool(self.dac_enabled);
+        self.length.save_state(writer);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.period);
+        writer.write_u32(self.delay);
+        writer.write_u8(self.volume_shift);
+        writer.write_bytes(&self.waveram);
+        writer.write_u8(self.current_wave);
+        writer.write_bool(self.sample_recently_accessed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.active = reader.read_bool();
+        self.dac_enabled = reader.read_bool();
+        self.length.load_state(reader);
+        self.frequency = reader.read_u16();
+        self.period = reader.read_u32();
+        self.delay = reader.read_u32();
+        self.volume_shift = reader.read_u8();
+        reader.read_bytes(&mut self.waveram);
+        self.current_wave = reader.read_u8();
+        self.sample_recently_accessed = reader.read_bool();
+        self.last_amp = 0;
+        self.blip.clear();
+    }
+}
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.active);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.reg_ff22);
+        self.length.save_state(writer);
+        self.volume_envelope.save_state(writer);
+        writer.write_u32(self.period);
+        writer.write_u8(self.shift_width);
+        writer.write_u16(self.state);
+        writer.write_u32(self.delay);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.active = reader.read_bool();
+        self.dac_enabled = reader.read_bool();
+        self.reg_ff22 = reader.read_u8();
+        self.length.load_state(reader);
+        self.volume_envelope.load_state(reader);
+        self.period = reader.read_u32();
+        self.shift_width = reader.read_u8();
+        self.state = reader.read_u16();
+        self.delay = reader.read_u32();
+        self.last_amp = 0;
+        self.blip.clear();
+    }
+}
+
+impl SaveState for Sound {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.on);
+        writer.write_u32(self.time);
+        writer.write_u32(self.prev_time);
+        writer.write_u32(self.next_time);
+        writer.write_u8(self.frame_step);
+        self.channel1.save_state(writer);
+        self.channel2.save_state(writer);
+        self.channel3.save_state(writer);
+        self.channel4.save_state(writer);
+        writer.write_u8(self.volume_left);
+        writer.write_u8(self.volume_right);
+        writer.write_u8(self.reg_vin_to_so);
+        writer.write_u8(self.reg_ff25);
+        writer.write_bytes(&self.written);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.on = reader.read_bool();
+        self.time = reader.read_u32();
+        self.prev_time = reader.read_u32();
+        self.next_time = reader.read_u32();
+        self.frame_step = reader.read_u8();
+        self.channel1.load_state(reader);
+        self.channel2.load_state(reader);
+        self.channel3.load_state(reader);
+        self.channel4.load_state(reader);
+        self.volume_left = reader.read_u8();
+        self.volume_right = reader.read_u8();
+        self.reg_vin_to_so = reader.read_u8();
+        self.reg_ff25 = reader.read_u8();
+        reader.read_bytes(&mut self.written);
+
+        // Drop the audio that was queued before the state was restored, otherwise the player keeps
+        // playing samples from a point in time that no longer exists.
+        self.player.clear_buffer();
+    }
+}
+
+/// The file a channel (0 to 3) stem is written to: "song.wav" becomes "song-ch1.wav" to "song-ch4.wav".
+pub fn stem_path(path: &Path, channel: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-ch{}.wav", stem, channel + 1))
+}
+
+fn create_blipbuf(samples_rate: u32) -> BlipBuf {
+    // Create a BlipBuf which can hold OUTPUT_SAMPLE_COUNT + 1 samples.
+    // Not sure why the +1 is needed. May need to correct the constant instead.
+    let mut blipbuf = BlipBuf::new((OUTPUT_SAMPLE_COUNT + 1) as u32);
+    blipbuf.set_rates(CLOCKS_PER_SECOND as f64, samples_rate as f64);
+    blipbuf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_register_to_note() {
+        // A square channel with frequency register $6D6 plays close to A4 (440 Hz)
+        let hz = period_to_hz((2048 - 0x6D6) * 4, 8);
+        let note = midi_note(hz);
+        assert_eq!(note.round() as i32, 69);
+        assert_eq!(note_name(69), "A4");
+        assert_eq!(note_name(60), "C4");
+        assert_eq!(note_name(0), "C-1");
+    }
+
+    #[test]
+    fn digital_outputs_follow_the_channels() {
+        let mut sound = Sound::new_cgb(Box::new(NullPlayer::new()));
+        sound.wb(0xFF26, 0x80);
+        assert_eq!(sound.pcm12(), 0x00);
+        // Channel 2 at 75% duty and full volume
+        sound.wb(0xFF16, 0xC0);
+        sound.wb(0xFF17, 0xF0);
+        sound.wb(0xFF19, 0x87);
+        let mut seen = Vec::new();
+        for _ in 0..4096 {
+            sound.do_cycle(4);
+            let pcm12 = sound.pcm12();
+            assert_eq!(pcm12 & 0x0F, 0);
+            if !seen.contains(&pcm12) {
+                seen.push(pcm12);
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, [0x00, 0xF0]);
+        assert_eq!(sound.pcm34(), 0x00);
+    }
+
+    #[test]
+    fn rate_adjustment_changes_sample_count() {
+        let player = RecordingPlayer::new(44100);
+        let samples = player.samples();
+        let mut sound = Sound::new_dmg(Box::new(player));
+        sound.wb(0xFF26, 0x80);
+        sound.set_rate_adjustment(1.1);
+        for _ in 0..CLOCKS_PER_SECOND / 4 {
+            sound.do_cycle(4);
+        }
+        let count = samples.lock().unwrap().len() as f64;
+        assert!((count - 44100.0 * 1.1).abs() < OUTPUT_SAMPLE_COUNT as f64, "{}", count);
+    }
+
+    #[test]
+    fn wav_recordings_keep_the_nominal_rate() {
+        let path = std::env::temp_dir().join("gb-core-nominal-rate.wav");
+        let mut sound = Sound::new_dmg(Box::new(RecordingPlayer::new(44100)));
+        sound.wb(0xFF26, 0x80);
+        sound.set_rate_adjustment(1.1);
+        sound.start_wav_recording(&path).unwrap();
+        assert_eq!(sound.rate_adjustment(), 1.0);
+        for _ in 0..CLOCKS_PER_SECOND / 4 {
+            sound.do_cycle(4);
+        }
+        sound.stop_wav_recording().unwrap();
+        assert_eq!(sound.rate_adjustment(), 1.1);
+
+        let frames = (std::fs::metadata(&path).unwrap().len() - 44) as f64 / 4.0;
+        let _ = std::fs::remove_file(path);
+        assert!((frames - 44100.0).abs() < OUTPUT_SAMPLE_COUNT as f64, "{}", frames);
+    }
+
+    #[test]
+    fn recording_keeps_the_latest_samples() {
+        let mut player = RecordingPlayer::with_limit(2, 2);
+        let samples = player.samples();
+        player.play(&[0.0, 0.1, 0.2], &[0.0, -0.1, -0.2]);
+        player.play(&[0.3, 0.4, 0.5], &[-0.3, -0.4, -0.5]);
+        let samples = samples.lock().unwrap();
+        assert_eq!(samples.len(), 4);
+        assert_eq!(samples.front(), Some(&(0.2, -0.2)));
+    }
+}
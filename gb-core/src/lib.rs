@@ -0,0 +1,28 @@
+//! Game Boy emulator core, free of any UI or audio backend so it can be embedded in other tools.
+//! `GameBoy` is the entry point, the modules expose the hardware for debuggers.
+
+pub mod assembler;
+pub mod cartridge;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod expression;
+pub mod gameboy;
+pub mod gbs;
+pub mod gbmode;
+pub mod headless;
+pub mod io;
+pub mod mbc;
+pub mod mmu;
+pub mod movie;
+pub mod ppu;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
+pub mod symbols;
+pub mod tracker;
+pub mod vgm;
+pub mod wav;
+
+pub use gameboy::{DebugHook, GameBoy, HookAction};
+
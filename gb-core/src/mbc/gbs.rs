@@ -0,0 +1,93 @@
+use anyhow::Result;
+
+use crate::gbs;
+use crate::mbc::MBC;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// Cartridge for GBS music files: the music data is laid out by `gbs::build_rom`, writes to
+/// 2000-3FFF select the ROM bank and 8 KB of RAM is always enabled.
+pub struct GbsMBC {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    selected_rom_bank: u8,
+    rom_bank_count: u8,
+}
+
+impl GbsMBC {
+    pub fn new(data: &[u8]) -> Result<Self> {
+        let rom = gbs::build_rom(data)?;
+        let rom_bank_count = (rom.len() / 0x4000).min(0xFF) as u8;
+        Ok(GbsMBC {
+            rom,
+            ram: vec![0; 0x2000],
+            selected_rom_bank: 1,
+            rom_bank_count,
+        })
+    }
+}
+
+impl MBC for GbsMBC {
+    fn force_write_rom(&mut self, address: u16, value: u8) {
+        self.rom[address as usize] = value;
+    }
+    fn has_battery(&self) -> bool {
+        false
+    }
+    fn load_ram(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+    fn dump_ram(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn get_rom(&self) -> &Vec<u8> {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        if address < 0x4000 {
+            self.rom[address as usize]
+        } else {
+            let offset = self.selected_rom_bank as usize * 0x4000 + (address as usize - 0x4000);
+            self.rom.get(offset).copied().unwrap_or(0xFF)
+        }
+    }
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram[(address & 0x1FFF) as usize]
+    }
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if let 0x2000..=0x3FFF = address {
+            self.selected_rom_bank = match value {
+                0 => 1,
+                n => n,
+            } % self.rom_bank_count;
+        }
+    }
+    fn write_ram(&mut self, address: u16, value: u8) {
+        self.ram[(address & 0x1FFF) as usize] = value;
+    }
+    fn has_register_at(&self, address: u16) -> bool {
+        (0x2000..=0x3FFF).contains(&address)
+    }
+
+    fn get_selected_rom_bank(&self) -> u8 {
+        self.selected_rom_bank
+    }
+    fn get_selected_ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn step(&mut self, _: u32) {}
+}
+
+impl SaveState for GbsMBC {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.ram);
+        writer.write_u8(self.selected_rom_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.ram = reader.read_vec();
+        self.selected_rom_bank = reader.read_u8();
+    }
+}
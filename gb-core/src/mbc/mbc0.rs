@@ -1,4 +1,5 @@
 use crate::mbc::MBC;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 pub struct MBC0 {
     rom: Vec<u8>,
@@ -36,6 +37,9 @@ impl MBC for MBC0 {
     }
     fn write_rom(&mut self, _: u16, _: u8) {}
     fn write_ram(&mut self, _: u16, _: u8) {}
+    fn has_register_at(&self, _: u16) -> bool {
+        false
+    }
 
     fn get_selected_rom_bank(&self) -> u8 {
         0
@@ -43,4 +47,11 @@ impl MBC for MBC0 {
     fn get_selected_ram_bank(&self) -> u8 {
         0
     }
+
+    fn step(&mut self, _: u32) {}
+}
+
+impl SaveState for MBC0 {
+    fn save_state(&self, _writer: &mut StateWriter) {}
+    fn load_state(&mut self, _reader: &mut StateReader) {}
 }
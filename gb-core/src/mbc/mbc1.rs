@@ -1,5 +1,6 @@
 use crate::mbc;
 use crate::mbc::MBC;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 use anyhow::{Result, anyhow};
 
@@ -129,10 +130,35 @@ impl MBC for MBC1 {
         }
     }
 
+    fn has_register_at(&self, _: u16) -> bool {
+        // Every range of 0000-7FFF is a register
+        true
+    }
+
     fn get_selected_rom_bank(&self) -> u8 {
         self.selected_rom_bank
     }
     fn get_selected_ram_bank(&self) -> u8 {
         self.selected_ram_bank
     }
+
+    fn step(&mut self, _: u32) {}
+}
+
+impl SaveState for MBC1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.selected_rom_bank);
+        writer.write_u8(self.selected_ram_bank);
+        writer.write_u8(self.banking_mode);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.ram = reader.read_vec();
+        self.ram_enabled = reader.read_bool();
+        self.selected_rom_bank = reader.read_u8();
+        self.selected_ram_bank = reader.read_u8();
+        self.banking_mode = reader.read_u8();
+    }
 }
@@ -1,8 +1,13 @@
 use crate::mbc;
 use crate::mbc::MBC;
-use std::{io::Write, time};
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use std::io::Write;
 use anyhow::{Result, anyhow};
 
+// The RTC runs from its own 32768 Hz crystal, but it is driven by the emulated clock instead of the
+// host's wall clock so that re-executing from a snapshot always produces the same register values.
+const RTC_CYCLES_PER_SECOND: u32 = 4194304;
+
 #[inline(always)]
 fn is_set(byte: u8, position: u8) -> bool {
     (byte >> position) & 1 == 1
@@ -18,7 +23,7 @@ pub struct MBC3 {
     rtc_selected: bool,
     rtc_ram: [u8; 5],
     rtc_ram_latch: [u8; 5],
-    rtc_zero: Option<u64>,
+    rtc_cycles: u32, // Cycles since the last RTC tick
     has_battery: bool,
 }
 
@@ -40,67 +45,44 @@ impl MBC3 {
             rtc_selected: false,
             rtc_ram: [0; 5],
             rtc_ram_latch: [0; 5],
-            rtc_zero: None,
+            rtc_cycles: 0,
             has_battery,
         }
     }
 
     fn latch_rtc_reg(&mut self) {
-        self.calc_rtc_reg();
         self.rtc_ram_latch.clone_from_slice(&self.rtc_ram);
     }
 
-    fn calc_rtc_reg(&mut self) {
-        // Do not modify regs when halted
-        if is_set(self.rtc_ram[4], 6) {
+    // Advances the clock by one second. Each counter only carries into the next one when it rolls over
+    // from its last valid value, out of range values written by the game count up to the register width.
+    fn tick_rtc(&mut self) {
+        self.rtc_ram[0] = (self.rtc_ram[0] + 1) & 0x3F;
+        if self.rtc_ram[0] != 60 {
             return;
         }
+        self.rtc_ram[0] = 0;
 
-        let tzero = match self.rtc_zero {
-            Some(t) => time::UNIX_EPOCH + time::Duration::from_secs(t),
-            None => return,
-        };
+        self.rtc_ram[1] = (self.rtc_ram[1] + 1) & 0x3F;
+        if self.rtc_ram[1] != 60 {
+            return;
+        }
+        self.rtc_ram[1] = 0;
 
-        if self.compute_time_diff() == self.rtc_zero {
-            // No time has passed. Do not alter registers
+        self.rtc_ram[2] = (self.rtc_ram[2] + 1) & 0x1F;
+        if self.rtc_ram[2] != 24 {
             return;
         }
+        self.rtc_ram[2] = 0;
 
-        let time_diff = match time::SystemTime::now().duration_since(tzero) {
-            Ok(n) => n.as_secs(),
-            _ => 0,
-        };
-        self.rtc_ram[0] = (time_diff % 60) as u8;
-        self.rtc_ram[1] = ((time_diff / 60) % 60) as u8;
-        self.rtc_ram[2] = ((time_diff / 3600) % 24) as u8;
-        let days = time_diff / (3600 * 24);
+        let days = (((self.rtc_ram[4] & 0x01) as u16) << 8 | self.rtc_ram[3] as u16) + 1;
         self.rtc_ram[3] = days as u8;
-        self.rtc_ram[4] = (self.rtc_ram[4] & 0xFE) | (((days >> 8) & 0x01) as u8);
+        self.rtc_ram[4] = (self.rtc_ram[4] & 0xFE) | ((days >> 8) & 0x01) as u8;
         if days >= 512 {
+            // Day counter overflow, the carry bit stays set until the game clears it
             self.rtc_ram[4] |= 0x80;
-            self.calc_rtc_zero();
         }
     }
-
-    fn compute_time_diff(&self) -> Option<u64> {
-        if self.rtc_zero.is_none() {
-            return None;
-        }
-        let mut time_diff = match time::SystemTime::now().duration_since(time::UNIX_EPOCH) {
-            Ok(t) => t.as_secs(),
-            Err(_) => panic!("System clock is set to a time before the unix epoch (1970-01-01)"),
-        };
-        time_diff -= self.rtc_ram[0] as u64;
-        time_diff -= (self.rtc_ram[1] as u64) * 60;
-        time_diff -= (self.rtc_ram[2] as u64) * 3600;
-        let days = ((self.rtc_ram[4] as u64 & 0x1) << 8) | (self.rtc_ram[3] as u64);
-        time_diff -= days * 3600 * 24;
-        Some(time_diff)
-    }
-
-    fn calc_rtc_zero(&mut self) {
-        self.rtc_zero = self.compute_time_diff();
-    }
 }
 
 impl MBC for MBC3 {
@@ -117,25 +99,21 @@ impl MBC for MBC3 {
             return Err(anyhow!("Loaded ram is too small"));
         }
 
-        let (int_bytes, rest) = data.split_at(8);
-        let rtc = u64::from_be_bytes(int_bytes.try_into().unwrap());
-        if self.rtc_zero.is_some() {
-            self.rtc_zero = Some(rtc);
-        }
+        // The first 8 bytes hold the RTC registers, older saves stored an (always zero) timestamp here
+        let (rtc_bytes, rest) = data.split_at(8);
+        self.rtc_ram.copy_from_slice(&rtc_bytes[..5]);
         self.ram = rest.to_vec();
         Ok(())
     }
 
     fn dump_ram(&self) -> Vec<u8> {
-        let rtc = match self.rtc_zero {
-            Some(t) => t,
-            None => 0,
-        };
+        let mut rtc_bytes = [0; 8];
+        rtc_bytes[..5].copy_from_slice(&self.rtc_ram);
 
         let mut file = vec![];
 
         let mut ok = true;
-        if ok { let rtc_bytes = rtc.to_be_bytes(); ok = file.write_all(&rtc_bytes).is_ok(); };
+        if ok { ok = file.write_all(&rtc_bytes).is_ok(); };
         if ok { let _ = file.write_all(&*self.ram); };
 
         file
@@ -204,7 +182,6 @@ impl MBC for MBC3 {
                 value;
             // self.ram[(self.selected_ram_bank as usize) * 0x2000 + (address as usize)] = value;
         } else if self.rtc_selected && self.selected_ram_bank < 5 {
-            self.calc_rtc_zero();
             let mask = match self.selected_ram_bank {
                 0 | 1 => 0x3F,
                 2 => 0x1F,
@@ -212,14 +189,58 @@ impl MBC for MBC3 {
                 _ => 0xFF,
             };
             self.rtc_ram[self.selected_ram_bank as usize] = value & mask;
-            self.calc_rtc_zero();
+            if self.selected_ram_bank == 0 {
+                // Writing the seconds resets the sub-second divider
+                self.rtc_cycles = 0;
+            }
         }
     }
 
+    fn has_register_at(&self, _: u16) -> bool {
+        true
+    }
+
     fn get_selected_rom_bank(&self) -> u8 {
         self.selected_rom_bank
     }
     fn get_selected_ram_bank(&self) -> u8 {
         self.selected_ram_bank
     }
+
+    fn step(&mut self, cycles: u32) {
+        // Do not modify regs when halted
+        if is_set(self.rtc_ram[4], 6) {
+            return;
+        }
+
+        self.rtc_cycles += cycles;
+        while self.rtc_cycles >= RTC_CYCLES_PER_SECOND {
+            self.rtc_cycles -= RTC_CYCLES_PER_SECOND;
+            self.tick_rtc();
+        }
+    }
+}
+
+impl SaveState for MBC3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.selected_rom_bank);
+        writer.write_u8(self.selected_ram_bank);
+        writer.write_bool(self.rtc_selected);
+        writer.write_bytes(&self.rtc_ram);
+        writer.write_bytes(&self.rtc_ram_latch);
+        writer.write_u32(self.rtc_cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.ram = reader.read_vec();
+        self.ram_enabled = reader.read_bool();
+        self.selected_rom_bank = reader.read_u8();
+        self.selected_ram_bank = reader.read_u8();
+        self.rtc_selected = reader.read_bool();
+        reader.read_bytes(&mut self.rtc_ram);
+        reader.read_bytes(&mut self.rtc_ram_latch);
+        self.rtc_cycles = reader.read_u32();
+    }
 }
@@ -1,13 +1,16 @@
 pub mod mbc3;
 pub mod mbc0;
 mod mbc1;
+mod gbs;
 
 use std::{fs, io::{Read, Write}, path};
 
 use anyhow::{Result, anyhow};
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 // https://gbdev.io/pandocs/MBCs.html
-pub trait MBC: Send {
+pub trait MBC: Send + SaveState {
     fn force_write_rom(&mut self, address: u16, value: u8);
     fn has_battery(&self) -> bool;
     fn load_ram(&mut self, data: &[u8]) -> Result<()>;
@@ -18,9 +21,14 @@ pub trait MBC: Send {
     fn read_ram(&self, address: u16) -> u8;
     fn write_rom(&mut self, address: u16, value: u8);
     fn write_ram(&mut self, address: u16, value: u8);
+    /// Whether a write to this ROM address reaches an MBC register.
+    fn has_register_at(&self, address: u16) -> bool;
 
     fn get_selected_rom_bank(&self) -> u8;
     fn get_selected_ram_bank(&self) -> u8;
+
+    /// Advances cartridge hardware that runs on its own clock, such as the MBC3 RTC.
+    fn step(&mut self, cycles: u32);
 }
 
 pub fn new_mbc(data: Vec<u8>) -> Box<dyn MBC> {
@@ -65,13 +73,18 @@ pub fn rom_bank_count(code: u8) -> u8 {
 pub struct FileBackedMBC {
     ram_path: std::path::PathBuf,
     mbc: Box<dyn MBC>,
+    pub persist_ram: bool, // Whether battery backed RAM is written back to the save file on drop
 }
 
 impl FileBackedMBC {
     pub fn new(rom_path: path::PathBuf) -> Result<FileBackedMBC> {
         let mut data = vec![];
         fs::File::open(&rom_path).and_then(|mut f| f.read_to_end(&mut data))?;
-        let mut mbc = new_mbc(data);
+        let mut mbc: Box<dyn MBC> = if data.starts_with(crate::gbs::MAGIC) {
+            Box::new(gbs::GbsMBC::new(&data)?)
+        } else {
+            new_mbc(data)
+        };
 
         let ram_path = rom_path.with_extension("gbsave");
 
@@ -89,7 +102,11 @@ impl FileBackedMBC {
             }
         }
 
-        Ok(FileBackedMBC { ram_path, mbc })
+        Ok(FileBackedMBC {
+            ram_path,
+            mbc,
+            persist_ram: true,
+        })
     }
 }
 
@@ -130,6 +147,10 @@ impl MBC for FileBackedMBC {
         self.mbc.write_ram(address, value);
     }
 
+    fn has_register_at(&self, address: u16) -> bool {
+        self.mbc.has_register_at(address)
+    }
+
     fn get_selected_rom_bank(&self) -> u8 {
         self.mbc.get_selected_rom_bank()
     }
@@ -137,11 +158,25 @@ impl MBC for FileBackedMBC {
     fn get_selected_ram_bank(&self) -> u8 {
         self.mbc.get_selected_ram_bank()
     }
+
+    fn step(&mut self, cycles: u32) {
+        self.mbc.step(cycles);
+    }
+}
+
+impl SaveState for FileBackedMBC {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.mbc.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.mbc.load_state(reader);
+    }
 }
 
 impl Drop for FileBackedMBC {
     fn drop(&mut self) {
-        if self.mbc.has_battery() {
+        if self.mbc.has_battery() && self.persist_ram {
             // TODO: error handling
             let mut file = match fs::File::create(&self.ram_path) {
                 Ok(f) => f,
@@ -1,12 +1,17 @@
 use crate::cartridge::Cartridge;
 use crate::gbmode::GbMode;
 use crate::io::joypad::Joypad;
+use crate::io::serial::Serial;
 use crate::mmu::timer::Timer;
 use crate::ppu::PPU;
 use crate::io::sound::{AudioPlayer, Sound};
-use crate::audio::CpalPlayer;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::mmu::watchpoint::{WatchHit, Watchpoint};
+use crate::debugger::{DebugEvent, DebugEvents, Exception};
+use crate::mbc::MBC;
 
 mod timer;
+pub mod watchpoint;
 
 #[inline(always)]
 fn bit(value: bool, position: u8) -> u8 {
@@ -330,6 +335,8 @@ pub struct MMU {
     boot_rom: Option<Vec<u8>>,
     wram: [[u8; 0x1000]; 8], // Working RAM, 8 banks total
     hram: [u8; 0x7F],        // aka High Ram or Zero Page
+    // One bit per WRAM byte written since power on, for the uninitialized read check
+    wram_written: [u8; 0x1000],
 
     hdma: [u8; 0x10],     // HDMA registers
     hdma_src: u16,        // HDMA source address
@@ -341,10 +348,18 @@ pub struct MMU {
     pub interrupt_flags: u8,  // 7-5: Unused, 4: Joypad, 3: Serial, 2: Timer, 1: LCD, 0: VBlank
     pub interrupt_enable: u8, // Controls whether the interrupt handler should be called, same layout as interrupt flags
     pub joypad: Joypad,
+    pub serial: Serial,
     pub ppu: PPU,
     pub timer: Timer,
     pub sound: Sound,
     gb_mode: GbMode,
+    pub watchpoints: Vec<Watchpoint>,
+    // Accesses that matched a watchpoint, collected until the debugger takes them
+    pub watch_hits: Vec<WatchHit>,
+    // PC of the instruction being executed while watchpoints or exception checks need it, None while
+    // accesses are not from an instruction, such as opcode fetches or debugger views
+    pub watch_pc: Option<u16>,
+    pub debug_events: DebugEvents,
 }
 
 impl MMU {
@@ -364,6 +379,7 @@ impl MMU {
             boot_rom,
             wram: [[0; 0x1000]; 8],
             hram: [0; 0x7F],
+            wram_written: [0; 0x1000],
 
             hdma: [0; 0x10],
             hdma_src: 0,
@@ -375,10 +391,15 @@ impl MMU {
             interrupt_flags: 0b00000,
             interrupt_enable: 0b00000,
             joypad: Joypad::new(),
+            serial: Serial::new(),
             ppu: PPU::new(gb_mode),
             timer: Timer::new(),
             sound,
             gb_mode,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            watch_pc: None,
+            debug_events: DebugEvents::new(),
         };
 
         mmu.write_byte(0xFF05, 0);
@@ -424,11 +445,16 @@ impl MMU {
         self.interrupt_flags |= self.timer.interrupt;
         self.timer.interrupt = 0;
 
+        self.serial.step(cycles);
+        self.interrupt_flags |= self.serial.interrupt;
+        self.serial.interrupt = 0;
+
         self.ppu.step(cycles);
         self.interrupt_flags |= self.ppu.interrupt;
         self.ppu.interrupt = 0;
 
         self.sound.do_cycle(cycles);
+        self.cartridge.step(cycles);
     }
 
     pub fn has_interrupt(&self) -> bool {
@@ -447,6 +473,37 @@ impl MMU {
     }
 
     pub fn read_byte(&mut self, address: u16) -> u8 {
+        let value = self.read_byte_unwatched(address);
+        if let Some(pc) = self.watch_pc {
+            for (watchpoint, _) in self.watchpoints.iter().enumerate().filter(|(_, w)| w.matches(address, false, value)) {
+                self.watch_hits.push(WatchHit {
+                    watchpoint,
+                    pc,
+                    address,
+                    write: false,
+                    old_value: value,
+                    new_value: value,
+                });
+            }
+            let written = |index: usize| is_set(self.wram_written[index / 8], index as u8 % 8);
+            if self.debug_events.checks.uninitialized_read && self.wram_index(address).is_some_and(|i| !written(i)) {
+                self.debug_events.report(Exception::UninitializedRead { address });
+            }
+        }
+        value
+    }
+
+    // Bit index into `wram_written`, echo RAM included
+    fn wram_index(&self, address: u16) -> Option<usize> {
+        match address {
+            0xC000..=0xCFFF => Some(address as usize - 0xC000),
+            0xD000..=0xDFFF => Some(self.selected_wram_bank as usize * 0x1000 + (address as usize - 0xD000)),
+            0xE000..=0xFDFF => Some(address as usize - 0xE000),
+            _ => None,
+        }
+    }
+
+    fn read_byte_unwatched(&mut self, address: u16) -> u8 {
         if let Some(boot_rom) = &self.boot_rom {
             if address < boot_rom.len() as u16 {
                 return boot_rom[address as usize];
@@ -469,8 +526,7 @@ impl MMU {
 
             // IO Registers: https://gbdev.io/pandocs/Hardware_Reg_List.html
             0xFF00 => self.joypad.read_byte(),
-            0xFF01 => 0,    // TODO: Serial Data Transfer
-            0xFF02 => 0,    // TODO: Serial Data Control
+            0xFF01..=0xFF02 => self.serial.read_byte(address),
             0xFF03 => 0xFF, // Unused
             0xFF04..=0xFF07 => self.timer.read_byte(address),
             0xFF08..=0xFF0E => 0xFF, // Unused
@@ -491,7 +547,9 @@ impl MMU {
             0xFF6D..=0xFF6F => 0xFF, // Unused
             0xFF70 => self.selected_wram_bank,
             0xFF71..=0xFF75 => 0xFF, // Unused
-            0xFF76..=0xFF77 => 0,    // TODO: Audio digital output
+            0xFF76 if self.gb_mode == GbMode::Color => self.sound.pcm12(),
+            0xFF77 if self.gb_mode == GbMode::Color => self.sound.pcm34(),
+            0xFF76..=0xFF77 => 0xFF, // CGB only
 
             0xFF78..=0xFF7F => 0xFF, // Unused
             0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80],
@@ -502,6 +560,79 @@ impl MMU {
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        if let Some(pc) = self.watch_pc {
+            if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(address, true, value)) {
+                let old_value = self.read_byte_unwatched(address);
+                let matching = self.watchpoints.iter().enumerate().filter(|(_, w)| w.matches(address, true, value));
+                for (watchpoint, _) in matching {
+                    self.watch_hits.push(WatchHit {
+                        watchpoint,
+                        pc,
+                        address,
+                        write: true,
+                        old_value,
+                        new_value: value,
+                    });
+                }
+            }
+            self.check_write(address, value);
+        }
+        if let Some(index) = self.wram_index(address) {
+            self.wram_written[index / 8] |= 1 << (index % 8);
+        }
+        if self.debug_events.enabled {
+            self.write_byte_with_events(address, value);
+        } else {
+            self.write_byte_unwatched(address, value);
+        }
+    }
+
+    // Reports the exceptions of a write by an instruction
+    fn check_write(&mut self, address: u16, value: u8) {
+        let checks = self.debug_events.checks;
+        let exception = match address {
+            0x0000..=0x7FFF if checks.rom_write && !self.cartridge.mbc.has_register_at(address) => {
+                Some(Exception::RomWrite { address, value })
+            }
+            0x8000..=0x9FFF if checks.vram_write && self.ppu.is_drawing() => Some(Exception::VramWrite { address }),
+            _ => None,
+        };
+        if let Some(exception) = exception {
+            self.debug_events.report(exception);
+        }
+    }
+
+    // Compares the state a write can change before and after it, to report the hardware events it caused
+    fn write_byte_with_events(&mut self, address: u16, value: u8) {
+        let bank = self.cartridge.mbc.get_selected_rom_bank();
+        let lcd_enabled = is_set(self.ppu.read_register(0xFF40), 7);
+        let hdma_running = self.hdma_status == DMAType::HDMA;
+        self.write_byte_unwatched(address, value);
+
+        let event = match address {
+            0x0000..=0x7FFF if self.cartridge.mbc.get_selected_rom_bank() != bank => Some(DebugEvent::BankSwitch {
+                bank: self.cartridge.mbc.get_selected_rom_bank(),
+            }),
+            0xFF40 if is_set(value, 7) != lcd_enabled => Some(DebugEvent::LcdEnabled(is_set(value, 7))),
+            0xFF46 => Some(DebugEvent::OamDma {
+                source: (value as u16) << 8,
+            }),
+            // Writing during an HBlank DMA only cancels it
+            0xFF55 if !hdma_running => Some(DebugEvent::Hdma {
+                source: self.hdma_src,
+                destination: self.hdma_dst,
+            }),
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.debug_events.push(event);
+        }
+        if (0xFF00..=0xFF7F).contains(&address) || address == 0xFFFF {
+            self.debug_events.push(DebugEvent::IoWrite { address, value });
+        }
+    }
+
+    fn write_byte_unwatched(&mut self, address: u16, value: u8) {
         if let Some(boot_rom) = &mut self.boot_rom {
             if address < boot_rom.len() as u16 {
                 boot_rom[address as usize] = value;
@@ -524,18 +655,18 @@ impl MMU {
 
             // IO Registers: https://gbdev.io/pandocs/Hardware_Reg_List.html
             0xFF00 => self.joypad.write_byte(value),
-            0xFF01 => {} // TODO: Serial Data Transfer
-            0xFF02 => {} // TODO: Serial Data Control
+            0xFF01..=0xFF02 => self.serial.write_byte(address, value),
             0xFF03 => {} // Unused
             0xFF04..=0xFF07 => self.timer.write_byte(address, value),
             0xFF08..=0xFF0E => {} // Unused
             0xFF0F => self.interrupt_flags = value,
             0xFF10..=0xFF3F => self.sound.wb(address, value), // TODO: Sound Registers
             0xFF46 => {
+                // The DMA unit copies, not the instruction, so watchpoints and exception checks ignore it
                 let base = (value as u16) << 8;
                 for i in 0..0xA0 {
-                    let b = self.read_byte(base + i);
-                    self.write_byte(0xFE00 + i, b);
+                    let b = self.read_byte_unwatched(base + i);
+                    self.write_byte_unwatched(0xFE00 + i, b);
                 }
             }
             0xFF40..=0xFF4B => self.ppu.write_register(address, value),
@@ -632,3 +763,69 @@ impl MMU {
         }
     }
 }
+
+impl SaveState for MMU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cartridge.mbc.save_state(writer);
+        match &self.boot_rom {
+            Some(boot_rom) => {
+                writer.write_bool(true);
+                writer.write_vec(boot_rom);
+            }
+            None => writer.write_bool(false),
+        }
+        for bank in &self.wram {
+            writer.write_bytes(bank);
+        }
+        writer.write_bytes(&self.hram);
+        writer.write_bytes(&self.wram_written);
+
+        writer.write_bytes(&self.hdma);
+        writer.write_u16(self.hdma_src);
+        writer.write_u16(self.hdma_dst);
+        writer.write_u8(self.hdma_len);
+        writer.write_u8(match self.hdma_status {
+            DMAType::NoDMA => 0,
+            DMAType::GDMA => 1,
+            DMAType::HDMA => 2,
+        });
+
+        writer.write_u8(self.selected_wram_bank);
+        writer.write_u8(self.interrupt_flags);
+        writer.write_u8(self.interrupt_enable);
+        self.joypad.save_state(writer);
+        self.serial.save_state(writer);
+        self.ppu.save_state(writer);
+        self.timer.save_state(writer);
+        self.sound.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.cartridge.mbc.load_state(reader);
+        self.boot_rom = if reader.read_bool() { Some(reader.read_vec()) } else { None };
+        for bank in &mut self.wram {
+            reader.read_bytes(bank);
+        }
+        reader.read_bytes(&mut self.hram);
+        reader.read_bytes(&mut self.wram_written);
+
+        reader.read_bytes(&mut self.hdma);
+        self.hdma_src = reader.read_u16();
+        self.hdma_dst = reader.read_u16();
+        self.hdma_len = reader.read_u8();
+        self.hdma_status = match reader.read_u8() {
+            1 => DMAType::GDMA,
+            2 => DMAType::HDMA,
+            _ => DMAType::NoDMA,
+        };
+
+        self.selected_wram_bank = reader.read_u8();
+        self.interrupt_flags = reader.read_u8();
+        self.interrupt_enable = reader.read_u8();
+        self.joypad.load_state(reader);
+        self.serial.load_state(reader);
+        self.ppu.load_state(reader);
+        self.timer.load_state(reader);
+        self.sound.load_state(reader);
+    }
+}
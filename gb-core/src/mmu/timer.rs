@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 pub struct Timer {
     divider: u8,
     counter: u8,
@@ -96,3 +98,27 @@ impl Timer {
         }
     }
 }
+
+impl SaveState for Timer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.divider);
+        writer.write_u8(self.counter);
+        writer.write_u8(self.modulo);
+        writer.write_bool(self.enabled);
+        writer.write_u32(self.step);
+        writer.write_u32(self.internal_counter);
+        writer.write_u32(self.internal_divider);
+        writer.write_u8(self.interrupt);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        self.divider = reader.read_u8();
+        self.counter = reader.read_u8();
+        self.modulo = reader.read_u8();
+        self.enabled = reader.read_bool();
+        self.step = reader.read_u32();
+        self.internal_counter = reader.read_u32();
+        self.internal_divider = reader.read_u32();
+        self.interrupt = reader.read_u8();
+    }
+}
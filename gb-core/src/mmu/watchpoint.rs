@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+
+use crate::expression::{BreakCondition, LogFormat};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    pub const ALL: [WatchKind; 3] = [WatchKind::Read, WatchKind::Write, WatchKind::ReadWrite];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchKind::Read => "Read",
+            WatchKind::Write => "Write",
+            WatchKind::ReadWrite => "Read/Write",
+        }
+    }
+}
+
+/// Stops execution when an instruction accesses memory in `start..=end`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    // Only hit when this value is read or written
+    pub value: Option<u8>,
+    /// Checked by the debugger after the accessing instruction
+    pub condition: BreakCondition,
+    /// Logs this message instead of stopping
+    pub log: Option<LogFormat>,
+}
+
+impl Watchpoint {
+    /// Parses an address range like "C000" or "C000-C0FF" in hexadecimal, and an optional hexadecimal value.
+    pub fn parse(range: &str, kind: WatchKind, value: &str) -> Result<Watchpoint> {
+        let parse_hex = |text: &str| u16::from_str_radix(text.trim().trim_start_matches('$'), 16);
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hex(start)?, parse_hex(end)?),
+            None => {
+                let address = parse_hex(range)?;
+                (address, address)
+            }
+        };
+        if start > end {
+            return Err(anyhow!("The range ends before it starts"));
+        }
+        let value = match value.trim() {
+            "" => None,
+            value => Some(u8::from_str_radix(value.trim_start_matches('$'), 16)?),
+        };
+        Ok(Watchpoint {
+            start,
+            end,
+            kind,
+            value,
+            condition: BreakCondition::default(),
+            log: None,
+        })
+    }
+
+    pub fn matches(&self, address: u16, write: bool, value: u8) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::ReadWrite => true,
+        };
+        kind_matches
+            && (self.start..=self.end).contains(&address)
+            && self.value.is_none_or(|expected| expected == value)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!("{} ${:04X}", self.kind.name(), self.start);
+        if self.end != self.start {
+            text += &format!("-${:04X}", self.end);
+        }
+        if let Some(value) = self.value {
+            text += &format!(" = ${:02X}", value);
+        }
+        text += &self.condition.describe();
+        if let Some(log) = &self.log {
+            text += &format!(" log \"{}\"", log.text());
+        }
+        text
+    }
+}
+
+/// A memory access that matched a watchpoint. For reads the old and new value are the value read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    // Index of the watchpoint in `MMU::watchpoints`
+    pub watchpoint: usize,
+    // Address of the instruction that made the access
+    pub pc: u16,
+    pub address: u16,
+    pub write: bool,
+    pub old_value: u8,
+    pub new_value: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    #[test]
+    fn parse_and_match() {
+        let watchpoint = Watchpoint::parse("C000-C0FF", WatchKind::Write, "42").unwrap();
+        assert!(watchpoint.matches(0xC010, true, 0x42));
+        assert!(!watchpoint.matches(0xC010, true, 0x41));
+        assert!(!watchpoint.matches(0xC010, false, 0x42));
+        assert!(!watchpoint.matches(0xC100, true, 0x42));
+        assert_eq!(watchpoint.describe(), "Write $C000-$C0FF = $42");
+
+        let watchpoint = Watchpoint::parse("$FF44", WatchKind::ReadWrite, "").unwrap();
+        assert!(watchpoint.matches(0xFF44, false, 0x90));
+        assert!(Watchpoint::parse("C100-C000", WatchKind::Read, "").is_err());
+    }
+
+    #[test]
+    fn oam_dma_does_not_hit() {
+        let mut cpu = test_cpu();
+        cpu.mmu.watchpoints.push(Watchpoint::parse("C100-C19F", WatchKind::ReadWrite, "").unwrap());
+        cpu.mmu.watchpoints.push(Watchpoint::parse("FE00-FE9F", WatchKind::Write, "").unwrap());
+        // ld a,$C1 / ldh [$46],a
+        for (offset, byte) in [0x3E, 0xC1, 0xE0, 0x46].into_iter().enumerate() {
+            cpu.mmu.write_byte(0xC000 + offset as u16, byte);
+        }
+        cpu.registers.pc = 0xC000;
+        cpu.step();
+        cpu.step();
+        assert!(cpu.mmu.watch_hits.is_empty());
+    }
+}
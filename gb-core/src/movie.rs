@@ -0,0 +1,232 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::cpu::CPU;
+use crate::mbc::MBC;
+use crate::savestate::{StateReader, StateWriter};
+
+// File layout, all integers little endian:
+// "GBMV", version (u8), ROM CRC-32 (u32), cartridge RAM (u32 length + bytes), frame count (u32),
+// then per frame the buttons (u8, see `Joypad::get_buttons`) and flags (u8, bit 0: reset before the frame)
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u8 = 1;
+const FLAG_RESET: u8 = 0x01;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MovieFrame {
+    pub buttons: u8,
+    pub reset: bool,
+}
+
+/// Input recording that replays a run exactly, starting from power on with the stored cartridge RAM.
+/// One entry is applied at every VBlank, so playback does not depend on how fast the UI repaints.
+#[derive(PartialEq, Debug)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub cartridge_ram: Vec<u8>,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(MAGIC);
+        writer.write_u8(VERSION);
+        writer.write_u32(self.rom_crc32);
+        writer.write_vec(&self.cartridge_ram);
+        writer.write_u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            writer.write_u8(frame.buttons);
+            writer.write_u8(if frame.reset { FLAG_RESET } else { 0 });
+        }
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie> {
+        let mut reader = StateReader::new(data);
+        if reader.remaining() < 9 {
+            return Err(anyhow!("Movie file is too small"));
+        }
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic);
+        if &magic != MAGIC {
+            return Err(anyhow!("Not a movie file"));
+        }
+        let version = reader.read_u8();
+        if version != VERSION {
+            return Err(anyhow!("Unsupported movie version: {}", version));
+        }
+        let rom_crc32 = reader.read_u32();
+
+        if reader.remaining() < 4 {
+            return Err(anyhow!("Movie file is truncated"));
+        }
+        let ram_len = reader.read_u32() as usize;
+        if reader.remaining() < ram_len + 4 {
+            return Err(anyhow!("Movie file is truncated"));
+        }
+        let mut cartridge_ram = vec![0; ram_len];
+        reader.read_bytes(&mut cartridge_ram);
+
+        let frame_count = reader.read_u32() as usize;
+        if reader.remaining() != frame_count * 2 {
+            return Err(anyhow!("Movie file has {} bytes of frame data, expected {}", reader.remaining(), frame_count * 2));
+        }
+        let frames = (0..frame_count)
+            .map(|_| MovieFrame {
+                buttons: reader.read_u8(),
+                reset: reader.read_u8() & FLAG_RESET != 0,
+            })
+            .collect();
+
+        Ok(Movie {
+            rom_crc32,
+            cartridge_ram,
+            frames,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Movie> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[derive(PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playback,
+}
+
+pub struct MovieSession {
+    pub mode: MovieMode,
+    pub movie: Movie,
+    pub path: PathBuf,
+    pub frame: usize, // Index of the next frame to record or play
+    reset_requested: bool,
+}
+
+impl MovieSession {
+    /// Powers the machine on with its current cartridge RAM and starts recording from there.
+    pub fn record(cpu: &mut CPU, path: PathBuf) -> Result<MovieSession> {
+        let movie = Movie {
+            rom_crc32: cpu.mmu.cartridge.compute_crc32(),
+            cartridge_ram: cpu.mmu.cartridge.mbc.dump_ram(),
+            frames: Vec::new(),
+        };
+        power_on(cpu, &movie.cartridge_ram)?;
+        Ok(MovieSession {
+            mode: MovieMode::Recording,
+            movie,
+            path,
+            frame: 0,
+            reset_requested: false,
+        })
+    }
+
+    /// Powers the machine on with the movie's cartridge RAM, fails if it was recorded with another ROM
+    /// or its RAM does not fit the cartridge.
+    pub fn play(cpu: &mut CPU, path: PathBuf) -> Result<MovieSession> {
+        let movie = Movie::load(&path)?;
+        let rom_crc32 = cpu.mmu.cartridge.compute_crc32();
+        if movie.rom_crc32 != rom_crc32 {
+            return Err(anyhow!(
+                "Movie was recorded with ROM {:08X}, but the loaded ROM is {:08X}",
+                movie.rom_crc32,
+                rom_crc32
+            ));
+        }
+        power_on(cpu, &movie.cartridge_ram)?;
+        Ok(MovieSession {
+            mode: MovieMode::Playback,
+            movie,
+            path,
+            frame: 0,
+            reset_requested: false,
+        })
+    }
+
+    /// Resets the machine at the next VBlank, so the reset lands on a frame boundary in the recording.
+    pub fn request_reset(&mut self) {
+        self.reset_requested = true;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.mode == MovieMode::Playback && self.frame >= self.movie.frames.len()
+    }
+
+    /// Called when the PPU enters VBlank, records `host_buttons` or applies the next recorded frame.
+    pub fn on_vblank(&mut self, cpu: &mut CPU, host_buttons: u8) {
+        let frame = match self.mode {
+            MovieMode::Recording => {
+                let frame = MovieFrame {
+                    buttons: host_buttons,
+                    reset: self.reset_requested,
+                };
+                self.reset_requested = false;
+                self.movie.frames.push(frame);
+                frame
+            }
+            MovieMode::Playback => match self.movie.frames.get(self.frame) {
+                Some(frame) => *frame,
+                None => return,
+            },
+        };
+        self.frame += 1;
+
+        if frame.reset {
+            cpu.reset();
+        }
+        cpu.mmu.joypad.set_buttons(frame.buttons);
+    }
+}
+
+fn power_on(cpu: &mut CPU, cartridge_ram: &[u8]) -> Result<()> {
+    cpu.power_on();
+    if cpu.mmu.cartridge.has_battery() {
+        // Cartridges without a battery dump no RAM, so there is nothing to restore
+        cpu.mmu.cartridge.mbc.load_ram(cartridge_ram)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::io::sound::NullPlayer;
+
+    #[test]
+    fn movie_roundtrip() {
+        let movie = Movie {
+            rom_crc32: 0xDEADBEEF,
+            cartridge_ram: vec![1, 2, 3],
+            frames: vec![
+                MovieFrame { buttons: 0x00, reset: false },
+                MovieFrame { buttons: 0x91, reset: true },
+            ],
+        };
+        let bytes = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+        assert!(Movie::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn playback_rejects_mismatched_cartridge_ram() {
+        let rom = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../roms/games/PokemonRed.gb"));
+        let mut cpu = CPU::new(Cartridge::new(rom), false, Box::new(NullPlayer::new()));
+        let path = std::env::temp_dir().join("gb-core-mismatched-ram.gbm");
+        let mut movie = MovieSession::record(&mut cpu, path.clone()).unwrap().movie;
+        movie.cartridge_ram.truncate(0x100);
+        movie.save(&path).unwrap();
+
+        assert!(MovieSession::play(&mut cpu, path.clone()).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+}
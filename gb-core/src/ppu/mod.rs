@@ -1,4 +1,5 @@
 use crate::gbmode::GbMode;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use std::cmp::Ordering;
 
 #[inline(always)]
@@ -73,6 +74,7 @@ pub struct PPU {
 
     pub screen_buffer: [u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 3],
     pub screen_buffer_updated: bool,
+    pub frame_count: u64, // Number of times the PPU entered VBlank since power on
     bg_priority: [PriorityType; SCREEN_WIDTH as usize],
     gb_mode: GbMode,
 }
@@ -124,6 +126,7 @@ impl PPU {
 
             screen_buffer: [0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 3],
             screen_buffer_updated: false,
+            frame_count: 0,
             bg_priority: [PriorityType::Normal; SCREEN_WIDTH as usize],
             gb_mode,
         }
@@ -209,6 +212,7 @@ impl PPU {
                     self.interrupt |= bit(true, 1);
                 }
                 self.screen_buffer_updated = true;
+                self.frame_count += 1;
             }
             2 => {
                 if self.mode_2_interrupt {
@@ -479,6 +483,16 @@ impl PPU {
         }
     }
 
+    /// The line being drawn, 144-153 during VBlank.
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    /// Whether the PPU is in mode 3, when it reads VRAM and the hardware ignores CPU writes to it.
+    pub fn is_drawing(&self) -> bool {
+        self.lcd_on && self.mode == 3
+    }
+
     pub fn read_vram(&self, addr: u16) -> u8 {
         assert!(addr <= 0x2000, "VRAM is only 0x2000 bytes long");
         self.vram[self.selected_vram_bank as usize][addr as usize]
@@ -687,3 +701,121 @@ impl PPU {
         }
     }
 }
+
+impl SaveState for PPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.vram[0]);
+        writer.write_bytes(&self.vram[1]);
+        writer.write_bytes(&self.oam);
+        writer.write_bool(self.selected_vram_bank);
+        writer.write_bool(self.lcd_on);
+        writer.write_u16(self.win_tilemap);
+        writer.write_bool(self.win_enabled);
+        writer.write_u16(self.tile_data_addr);
+        writer.write_u16(self.bg_tilemap_addr);
+        writer.write_u8(self.sprite_size);
+        writer.write_bool(self.sprite_enabled);
+        writer.write_bool(self.bg_enabled);
+
+        writer.write_bool(self.lyc_interrupt);
+        writer.write_bool(self.mode_2_interrupt);
+        writer.write_bool(self.mode_1_interrupt);
+        writer.write_bool(self.mode_0_interrupt);
+
+        writer.write_u8(self.ly);
+        writer.write_u8(self.lyc);
+        writer.write_u8(self.mode);
+        writer.write_u8(self.scy);
+        writer.write_u8(self.scx);
+        writer.write_u8(self.bg_palette);
+        writer.write_u8(self.obj_palette0);
+        writer.write_u8(self.obj_palette1);
+        writer.write_u8(self.winy);
+        writer.write_u8(self.winx);
+
+        writer.write_bool(self.cbg_palette_auto_increment);
+        writer.write_u8(self.cbg_palette_index);
+        writer.write_bool(self.cobj_palette_auto_increment);
+        writer.write_u8(self.cobj_palette_index);
+        for palette in self.cbg_palette.iter().chain(self.cobj_palette.iter()) {
+            for color in palette {
+                writer.write_bytes(color);
+            }
+        }
+
+        writer.write_bool(self.wy_trigger);
+        writer.write_i16(self.wy_pos);
+        writer.write_u8(self.interrupt);
+        writer.write_bool(self.hblank);
+        writer.write_u16(self.dots);
+
+        writer.write_bytes(&self.screen_buffer);
+        writer.write_bool(self.screen_buffer_updated);
+        writer.write_u64(self.frame_count);
+        for priority in &self.bg_priority {
+            writer.write_u8(match priority {
+                PriorityType::Color0 => 0,
+                PriorityType::PriorityFlag => 1,
+                PriorityType::Normal => 2,
+            });
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) {
+        reader.read_bytes(&mut self.vram[0]);
+        reader.read_bytes(&mut self.vram[1]);
+        reader.read_bytes(&mut self.oam);
+        self.selected_vram_bank = reader.read_bool();
+        self.lcd_on = reader.read_bool();
+        self.win_tilemap = reader.read_u16();
+        self.win_enabled = reader.read_bool();
+        self.tile_data_addr = reader.read_u16();
+        self.bg_tilemap_addr = reader.read_u16();
+        self.sprite_size = reader.read_u8();
+        self.sprite_enabled = reader.read_bool();
+        self.bg_enabled = reader.read_bool();
+
+        self.lyc_interrupt = reader.read_bool();
+        self.mode_2_interrupt = reader.read_bool();
+        self.mode_1_interrupt = reader.read_bool();
+        self.mode_0_interrupt = reader.read_bool();
+
+        self.ly = reader.read_u8();
+        self.lyc = reader.read_u8();
+        self.mode = reader.read_u8();
+        self.scy = reader.read_u8();
+        self.scx = reader.read_u8();
+        self.bg_palette = reader.read_u8();
+        self.obj_palette0 = reader.read_u8();
+        self.obj_palette1 = reader.read_u8();
+        self.winy = reader.read_u8();
+        self.winx = reader.read_u8();
+
+        self.cbg_palette_auto_increment = reader.read_bool();
+        self.cbg_palette_index = reader.read_u8();
+        self.cobj_palette_auto_increment = reader.read_bool();
+        self.cobj_palette_index = reader.read_u8();
+        for palette in self.cbg_palette.iter_mut().chain(self.cobj_palette.iter_mut()) {
+            for color in palette {
+                reader.read_bytes(color);
+            }
+        }
+
+        self.wy_trigger = reader.read_bool();
+        self.wy_pos = reader.read_i16();
+        self.interrupt = reader.read_u8();
+        self.hblank = reader.read_bool();
+        self.dots = reader.read_u16();
+
+        reader.read_bytes(&mut self.screen_buffer);
+        self.screen_buffer_updated = reader.read_bool();
+        self.frame_count = reader.read_u64();
+        for priority in &mut self.bg_priority {
+            *priority = match reader.read_u8() {
+                0 => PriorityType::Color0,
+                1 => PriorityType::PriorityFlag,
+                _ => PriorityType::Normal,
+            };
+        }
+    }
+}
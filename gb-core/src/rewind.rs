@@ -0,0 +1,366 @@
+use std::collections::VecDeque;
+
+use crate::cpu::CPU;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+struct Snapshot {
+    frame: u64,
+    instruction_count: u64,
+    data: Vec<u8>,
+}
+
+struct RewindEntry {
+    frame: u64,
+    instruction_count: u64,
+    delta: Vec<u8>, // Delta that turns the next (newer) snapshot back into this one
+}
+
+/// Ring buffer of machine snapshots, captured every `frame_interval` frames.
+/// Only the newest snapshot is kept in full, every older one is stored as a delta against the snapshot
+/// that came after it. Rewinding walks the chain backwards, and the oldest entries are dropped once the
+/// memory budget is exceeded, as nothing newer depends on them.
+pub struct RewindBuffer {
+    pub enabled: bool,
+    pub frame_interval: u64,
+    pub memory_budget: usize,
+    latest: Option<Snapshot>,
+    history: VecDeque<RewindEntry>,
+    history_size: usize,
+    next_capture_frame: u64,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RewindBuffer {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            frame_interval: 1,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            latest: None,
+            history: VecDeque::new(),
+            history_size: 0,
+            next_capture_frame: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.history.clear();
+        self.history_size = 0;
+        self.next_capture_frame = 0;
+    }
+
+    pub fn snapshot_count(&self) -> usize {
+        self.history.len() + self.latest.is_some() as usize
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.history_size + self.latest.as_ref().map_or(0, |snapshot| snapshot.data.len())
+    }
+
+    /// Called after every executed instruction, captures a snapshot once a new frame is due.
+    pub fn on_step(&mut self, cpu: &CPU) {
+        if self.enabled && cpu.mmu.ppu.frame_count >= self.next_capture_frame {
+            self.capture(cpu);
+        }
+    }
+
+    pub fn capture(&mut self, cpu: &CPU) {
+        let frame = cpu.mmu.ppu.frame_count;
+        let mut writer = StateWriter::new();
+        cpu.save_state(&mut writer);
+        let data = writer.into_bytes();
+
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&data, &previous.data);
+            self.history_size += delta.len();
+            self.history.push_back(RewindEntry {
+                frame: previous.frame,
+                instruction_count: previous.instruction_count,
+                delta,
+            });
+        }
+        self.latest = Some(Snapshot {
+            frame,
+            instruction_count: cpu.instruction_count,
+            data,
+        });
+        self.next_capture_frame = frame + self.frame_interval.max(1);
+        self.trim();
+    }
+
+    /// Restores the newest snapshot and removes it from the buffer, so calling this repeatedly steps
+    /// further back in time. Returns false when there is nothing left to rewind to.
+    pub fn rewind(&mut self, cpu: &mut CPU) -> bool {
+        let Some(snapshot) = self.pop_latest() else {
+            return false;
+        };
+
+        cpu.load_state(&mut StateReader::new(&snapshot.data));
+        self.next_capture_frame = snapshot.frame + self.frame_interval.max(1);
+        true
+    }
+
+    /// Restores the newest snapshot taken at or before `instruction_count`, dropping every newer one.
+    /// The restored snapshot stays in the buffer, so re-executing from it captures the dropped ones again.
+    /// Returns false when no snapshot is old enough.
+    pub fn restore_before(&mut self, cpu: &mut CPU, instruction_count: u64) -> bool {
+        while let Some(snapshot) = &self.latest {
+            if snapshot.instruction_count <= instruction_count {
+                cpu.load_state(&mut StateReader::new(&snapshot.data));
+                self.next_capture_frame = snapshot.frame + self.frame_interval.max(1);
+                return true;
+            }
+            self.pop_latest();
+        }
+        false
+    }
+
+    // Removes the newest snapshot, reconstructing the one before it from its delta
+    fn pop_latest(&mut self) -> Option<Snapshot> {
+        let snapshot = self.latest.take()?;
+        if let Some(entry) = self.history.pop_back() {
+            self.history_size -= entry.delta.len();
+            self.latest = Some(Snapshot {
+                frame: entry.frame,
+                instruction_count: entry.instruction_count,
+                data: apply_delta(&snapshot.data, &entry.delta),
+            });
+        }
+        Some(snapshot)
+    }
+
+    pub fn trim(&mut self) {
+        while self.memory_usage() > self.memory_budget {
+            let Some(entry) = self.history.pop_front() else {
+                break;
+            };
+            self.history_size -= entry.delta.len();
+        }
+    }
+}
+
+/// Joypad changes keyed by the instruction count they were applied at, so that re-executing from a
+/// snapshot sees exactly the same input as the original run.
+pub struct InputLog {
+    changes: Vec<(u64, u8)>,
+}
+
+impl Default for InputLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputLog {
+    pub fn new() -> Self {
+        Self { changes: Vec::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Records the buttons (see `Joypad::get_buttons`) applied before executing `instruction_count`.
+    /// Anything recorded later than that belongs to a timeline that was abandoned by stepping back.
+    pub fn record(&mut self, instruction_count: u64, buttons: u8) {
+        self.truncate_after(instruction_count);
+        if let Some(last) = self.changes.last_mut() {
+            if last.0 == instruction_count {
+                last.1 = buttons;
+                return;
+            }
+        }
+        self.changes.push((instruction_count, buttons));
+    }
+
+    pub fn truncate_after(&mut self, instruction_count: u64) {
+        while self.changes.last().is_some_and(|(count, _)| *count > instruction_count) {
+            self.changes.pop();
+        }
+    }
+
+    /// Returns the buttons that were applied right before executing `instruction_count`, if they changed.
+    pub fn change_at(&self, instruction_count: u64) -> Option<u8> {
+        self.changes
+            .binary_search_by_key(&instruction_count, |(count, _)| *count)
+            .ok()
+            .map(|index| self.changes[index].1)
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Encodes `target` relative to `base` as a list of (unchanged run, changed bytes) pairs.
+/// Bytes past the end of `base` are compared against 0, so snapshots may differ in size.
+pub fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let base_byte = |i: usize| base.get(i).copied().unwrap_or(0);
+
+    let mut output = Vec::new();
+    write_varint(&mut output, target.len());
+    let mut i = 0;
+    while i < target.len() {
+        let unchanged_start = i;
+        while i < target.len() && target[i] == base_byte(i) {
+            i += 1;
+        }
+        let changed_start = i;
+        while i < target.len() && target[i] != base_byte(i) {
+            i += 1;
+        }
+        write_varint(&mut output, changed_start - unchanged_start);
+        write_varint(&mut output, i - changed_start);
+        output.extend_from_slice(&target[changed_start..i]);
+    }
+    output
+}
+
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let len = read_varint(delta, &mut position);
+    let mut output = Vec::with_capacity(len);
+    while output.len() < len {
+        let unchanged = read_varint(delta, &mut position);
+        for i in output.len()..output.len() + unchanged {
+            output.push(base.get(i).copied().unwrap_or(0));
+        }
+        let changed = read_varint(delta, &mut position);
+        output.extend_from_slice(&delta[position..position + changed]);
+        position += changed;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    fn save(cpu: &CPU) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        cpu.save_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    fn run_until_frame(cpu: &mut CPU, frame: u64) {
+        while cpu.mmu.ppu.frame_count < frame {
+            cpu.step();
+        }
+    }
+
+    #[test]
+    fn delta_roundtrip() {
+        let base = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let target = vec![1, 2, 9, 9, 5, 6, 7, 0];
+        let delta = encode_delta(&base, &target);
+        assert_eq!(apply_delta(&base, &delta), target);
+    }
+
+    #[test]
+    fn delta_roundtrip_different_sizes() {
+        let base = vec![0xAA; 300];
+        let longer = [vec![0xAA; 200], vec![0x00; 150], vec![0x11; 10]].concat();
+        assert_eq!(apply_delta(&base, &encode_delta(&base, &longer)), longer);
+
+        let shorter = vec![0xAA; 20];
+        assert_eq!(apply_delta(&base, &encode_delta(&base, &shorter)), shorter);
+    }
+
+    #[test]
+    fn unchanged_data_compresses() {
+        let base = vec![0x42; 0x10000];
+        let delta = encode_delta(&base, &base);
+        assert!(delta.len() < 8, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&base, &delta), base);
+    }
+
+    #[test]
+    fn rewind_restores_machine_state() {
+        let mut cpu = test_cpu();
+        let mut buffer = RewindBuffer::new();
+
+        run_until_frame(&mut cpu, 10);
+        buffer.capture(&cpu);
+        let expected = save(&cpu);
+
+        run_until_frame(&mut cpu, 20);
+        buffer.capture(&cpu);
+        assert_eq!(buffer.snapshot_count(), 2);
+
+        assert!(buffer.rewind(&mut cpu));
+        assert_eq!(cpu.mmu.ppu.frame_count, 20);
+        assert!(buffer.rewind(&mut cpu));
+        assert_eq!(save(&cpu), expected);
+        assert!(!buffer.rewind(&mut cpu));
+    }
+
+    #[test]
+    fn restore_before_reexecutes_deterministically() {
+        let mut cpu = test_cpu();
+        let mut buffer = RewindBuffer::new();
+
+        while cpu.mmu.ppu.frame_count < 5 {
+            cpu.step();
+            buffer.on_step(&cpu);
+        }
+        for _ in 0..1000 {
+            cpu.step();
+            buffer.on_step(&cpu);
+        }
+        let target = cpu.instruction_count - 1;
+        let expected = save(&cpu);
+
+        assert!(buffer.restore_before(&mut cpu, target));
+        assert!(cpu.instruction_count <= target);
+        while cpu.instruction_count < target + 1 {
+            cpu.step();
+            buffer.on_step(&cpu);
+        }
+        assert_eq!(save(&cpu), expected);
+    }
+
+    #[test]
+    fn input_log_truncates_abandoned_timeline() {
+        let mut log = InputLog::new();
+        log.record(10, 0x01);
+        log.record(20, 0x00);
+        log.record(30, 0x80);
+        assert_eq!(log.change_at(20), Some(0x00));
+
+        log.record(15, 0x10);
+        assert_eq!(log.change_at(15), Some(0x10));
+        assert_eq!(log.change_at(20), None);
+        assert_eq!(log.change_at(30), None);
+    }
+}
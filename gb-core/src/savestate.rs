@@ -0,0 +1,122 @@
+/// Serializes the emulated machine into a flat byte buffer, used for rewind snapshots.
+/// The layout is not versioned, a snapshot can only be restored by the same build that created it.
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader);
+}
+
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Writes a length prefixed byte buffer, for data that can change size such as cartridge RAM.
+    pub fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn read_u8(&mut self) -> u8 {
+        let value = self.data[self.position];
+        self.position += 1;
+        value
+    }
+
+    pub fn read_bool(&mut self) -> bool {
+        self.read_u8() != 0
+    }
+
+    pub fn read_u16(&mut self) -> u16 {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes);
+        u16::from_le_bytes(bytes)
+    }
+
+    pub fn read_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    pub fn read_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    pub fn read_i16(&mut self) -> i16 {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes);
+        i16::from_le_bytes(bytes)
+    }
+
+    pub fn read_bytes(&mut self, destination: &mut [u8]) {
+        let end = self.position + destination.len();
+        destination.copy_from_slice(&self.data[self.position..end]);
+        self.position = end;
+    }
+
+    pub fn read_vec(&mut self) -> Vec<u8> {
+        let len = self.read_u32() as usize;
+        let mut bytes = vec![0; len];
+        self.read_bytes(&mut bytes);
+        bytes
+    }
+}
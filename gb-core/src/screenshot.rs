@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// An RGB image, the same layout as `PPU::screen_buffer`.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn from_screen_buffer(screen_buffer: &[u8]) -> Screenshot {
+        Screenshot {
+            width: SCREEN_WIDTH as u32,
+            height: SCREEN_HEIGHT as u32,
+            data: screen_buffer.to_vec(),
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.data)?;
+        Ok(())
+    }
+
+    /// Loads a PNG, converting grayscale and alpha images to plain RGB.
+    pub fn load_png(path: &Path) -> Result<Screenshot> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let pixels = &buffer[..info.buffer_size()];
+
+        let data = match info.color_type {
+            png::ColorType::Rgb => pixels.to_vec(),
+            png::ColorType::Rgba => pixels.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p]).collect(),
+            png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+            png::ColorType::Indexed => return Err(anyhow!("Indexed PNGs should have been expanded")),
+        };
+        Ok(Screenshot {
+            width: info.width,
+            height: info.height,
+            data,
+        })
+    }
+
+    /// Compares against `expected` pixel by pixel. On a mismatch returns the number of differing pixels
+    /// and a diff image: differing pixels in red, matching ones as a faded copy of the expected image.
+    pub fn diff(&self, expected: &Screenshot) -> Option<(usize, Screenshot)> {
+        if self.width != expected.width || self.height != expected.height {
+            let pixels = (expected.width * expected.height) as usize;
+            let diff = Screenshot {
+                width: expected.width,
+                height: expected.height,
+                data: [0xFF, 0x00, 0x00].repeat(pixels),
+            };
+            return Some((pixels, diff));
+        }
+
+        let mut mismatches = 0;
+        let mut data = Vec::with_capacity(self.data.len());
+        for (actual, expected) in self.data.chunks(3).zip(expected.data.chunks(3)) {
+            if actual == expected {
+                let gray = ((expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 3) as u8;
+                let faded = 0x80 + gray / 2;
+                data.extend_from_slice(&[faded, faded, faded]);
+            } else {
+                mismatches += 1;
+                data.extend_from_slice(&[0xFF, 0x00, 0x00]);
+            }
+        }
+
+        if mismatches == 0 {
+            return None;
+        }
+        Some((
+            mismatches,
+            Screenshot {
+                width: self.width,
+                height: self.height,
+                data,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_marks_changed_pixels() {
+        let expected = Screenshot {
+            width: 2,
+            height: 1,
+            data: vec![0, 0, 0, 255, 255, 255],
+        };
+        let actual = Screenshot {
+            width: 2,
+            height: 1,
+            data: vec![0, 0, 0, 0, 0, 0],
+        };
+        assert!(expected.diff(&expected).is_none());
+
+        let (mismatches, diff) = actual.diff(&expected).unwrap();
+        assert_eq!(mismatches, 1);
+        assert_eq!(&diff.data[3..], &[0xFF, 0x00, 0x00]);
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Labels from a RGBDS or bgb symbol file, one "BB:AAAA Name" entry per line.
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, (u8, u16)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn parse(text: &str) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut parts = line.split_whitespace();
+            let (Some(location), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            if let (Ok(bank), Ok(address)) = (u8::from_str_radix(bank, 16), u16::from_str_radix(address, 16)) {
+                table.symbols.insert(name.to_owned(), (bank, address));
+            }
+        }
+        table
+    }
+
+    pub fn load(path: &Path) -> Result<SymbolTable> {
+        Ok(SymbolTable::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Bank and address of the symbol.
+    pub fn get(&self, name: &str) -> Option<(u8, u16)> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::io::sound::{midi_note, ChannelStatus};
+
+/// CPU cycles per LCD frame, used to timestamp events in frames.
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+// One MIDI tick per frame: 60 ticks per quarter note, with a quarter note lasting 60 frames
+const MIDI_DIVISION: u16 = 60;
+const MIDI_TEMPO: u32 = 60 * 16743; // microseconds per quarter note
+const NOISE_DRUM_NOTE: u8 = 38;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoteEventKind {
+    Trigger,
+    Frequency,
+    Volume,
+    // The channel went silent by itself, through its length counter or sweep overflow
+    Stop,
+}
+
+/// A change on one channel, with the channel state right after it.
+#[derive(Clone, Debug)]
+pub struct NoteEvent {
+    pub cycle: u64,
+    pub channel: u8,
+    pub kind: NoteEventKind,
+    pub frequency_hz: f32,
+    // 0-15, for the wave channel derived from the output level
+    pub volume: u8,
+}
+
+impl NoteEvent {
+    pub fn frame(&self) -> u64 {
+        self.cycle / CYCLES_PER_FRAME
+    }
+}
+
+/// A played note reconstructed from the events. `note` is the nearest MIDI note, the noise channel uses
+/// `NOISE_DRUM_NOTE`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    pub channel: u8,
+    pub start_frame: u64,
+    pub end_frame: u64,
+    pub note: u8,
+    pub volume: u8,
+}
+
+/// Log of APU channel triggers, frequency and volume changes, filled by `Sound` while enabled.
+pub struct MusicLog {
+    pub events: Vec<NoteEvent>,
+    last: [(f32, u8, bool); 4],
+}
+
+impl Default for MusicLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MusicLog {
+    pub fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            last: [(0.0, 0, false); 4],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Records the state of `channel` after a register write, only changes are kept unless `triggered`.
+    pub fn record(&mut self, cycle: u64, channel: usize, status: &ChannelStatus, triggered: bool) {
+        let volume = Self::volume(channel, status);
+        let (last_hz, last_volume, _) = self.last[channel];
+        let kind = if triggered {
+            NoteEventKind::Trigger
+        } else if status.frequency_hz != last_hz {
+            NoteEventKind::Frequency
+        } else if volume != last_volume {
+            NoteEventKind::Volume
+        } else {
+            return;
+        };
+        self.push(cycle, channel, kind, status.frequency_hz, volume, status.active);
+    }
+
+    /// Records a Stop event when `channel` turned off since the last event.
+    pub fn check_stopped(&mut self, cycle: u64, channel: usize, active: bool) {
+        let (hz, volume, was_active) = self.last[channel];
+        if was_active && !active {
+            self.push(cycle, channel, NoteEventKind::Stop, hz, volume, false);
+        }
+    }
+
+    fn push(&mut self, cycle: u64, channel: usize, kind: NoteEventKind, frequency_hz: f32, volume: u8, active: bool) {
+        self.last[channel] = (frequency_hz, volume, active);
+        self.events.push(NoteEvent {
+            cycle,
+            channel: channel as u8,
+            kind,
+            frequency_hz,
+            volume,
+        });
+    }
+
+    fn volume(channel: usize, status: &ChannelStatus) -> u8 {
+        if channel == 2 {
+            [0, 15, 8, 4][status.volume as usize & 3]
+        } else {
+            status.volume
+        }
+    }
+
+    /// Turns the events into notes: a note starts on a trigger and lasts until the channel stops,
+    /// is muted or retriggered. A frequency change while playing starts a new note.
+    /// Notes shorter than a frame are dropped.
+    pub fn notes(&self) -> Vec<Note> {
+        let mut notes = Vec::new();
+        let mut playing: [Option<Note>; 4] = Default::default();
+        let end_frame = self.events.last().map_or(0, |event| event.frame() + 1);
+
+        for event in &self.events {
+            let channel = event.channel as usize;
+            let frame = event.frame();
+            let note = if channel == 3 {
+                NOISE_DRUM_NOTE as f32
+            } else {
+                midi_note(event.frequency_hz).round()
+            };
+            let audible = event.volume > 0 && event.frequency_hz > 0.0 && (0.0..128.0).contains(&note);
+
+            let starts_note = match event.kind {
+                NoteEventKind::Trigger => audible,
+                NoteEventKind::Frequency | NoteEventKind::Volume => {
+                    audible
+                        && playing[channel]
+                            .as_ref()
+                            .is_some_and(|current| current.note != note as u8)
+                }
+                NoteEventKind::Stop => false,
+            };
+            let ends_note = starts_note || !audible || event.kind == NoteEventKind::Stop;
+
+            if ends_note {
+                // Games often write several registers per note within a frame, only keep what was heard
+                if let Some(current) = playing[channel].take().filter(|current| current.start_frame < frame) {
+                    notes.push(Note {
+                        end_frame: frame,
+                        ..current
+                    });
+                }
+            }
+            if starts_note {
+                playing[channel] = Some(Note {
+                    channel: event.channel,
+                    start_frame: frame,
+                    end_frame: frame,
+                    note: note as u8,
+                    volume: event.volume,
+                });
+            }
+        }
+
+        for current in playing.into_iter().flatten() {
+            notes.push(Note { end_frame, ..current });
+        }
+        notes.sort_by_key(|note| note.start_frame);
+        notes
+    }
+
+    /// A type 0 MIDI file of `notes`, one tick per frame. APU channels map to MIDI channels 1-3,
+    /// the noise channel to the percussion channel 10.
+    pub fn to_midi(&self) -> Vec<u8> {
+        // (tick, is note on, channel, note, velocity), note offs sort before note ons on the same tick
+        let mut messages = Vec::new();
+        for note in self.notes() {
+            let channel = if note.channel == 3 { 9 } else { note.channel };
+            let velocity = (note.volume as u32 * 127 / 15).max(1) as u8;
+            messages.push((note.start_frame, true, channel, note.note, velocity));
+            messages.push((note.end_frame, false, channel, note.note, 0));
+        }
+        messages.sort_by_key(|&(tick, is_on, ..)| (tick, is_on));
+
+        let mut track = Vec::new();
+        // Tempo
+        track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+        track.extend_from_slice(&MIDI_TEMPO.to_be_bytes()[1..]);
+        // Square lead for the square channels, sawtooth for the wave channel
+        for (channel, program) in [(0u8, 80u8), (1, 80), (2, 81)] {
+            track.extend_from_slice(&[0x00, 0xC0 | channel, program]);
+        }
+
+        let mut last_tick = 0;
+        for (tick, is_on, channel, note, velocity) in messages {
+            write_variable_length(&mut track, (tick - last_tick) as u32);
+            last_tick = tick;
+            let status = if is_on { 0x90 } else { 0x80 };
+            track.extend_from_slice(&[status | channel, note, velocity]);
+        }
+        track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&MIDI_DIVISION.to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+        bytes
+    }
+
+    pub fn save_midi(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_midi())?;
+        Ok(())
+    }
+}
+
+fn write_variable_length(output: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    output.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(frequency_hz: f32, volume: u8, active: bool) -> ChannelStatus {
+        ChannelStatus {
+            active,
+            dac_enabled: true,
+            length_enabled: false,
+            length: 0,
+            volume,
+            envelope: None,
+            duty: Some(2),
+            sweep: None,
+            frequency: None,
+            noise: None,
+            frequency_hz,
+        }
+    }
+
+    #[test]
+    fn events_become_notes() {
+        let mut log = MusicLog::new();
+        log.record(0, 0, &square(440.0, 15, true), true);
+        // Same state is not logged again
+        log.record(100, 0, &square(440.0, 15, true), false);
+        log.record(10 * CYCLES_PER_FRAME, 0, &square(880.0, 15, true), false);
+        log.check_stopped(20 * CYCLES_PER_FRAME, 0, false);
+        assert_eq!(log.events.len(), 3);
+
+        let notes = log.notes();
+        assert_eq!(notes.len(), 2);
+        assert_eq!((notes[0].note, notes[0].start_frame, notes[0].end_frame), (69, 0, 10));
+        assert_eq!((notes[1].note, notes[1].start_frame, notes[1].end_frame), (81, 10, 20));
+
+        let midi = log.to_midi();
+        assert_eq!(&midi[0..4], b"MThd");
+        assert!(midi.ends_with(&[0x00, 0xFF, 0x2F, 0x00]));
+    }
+
+    #[test]
+    fn variable_length_quantities() {
+        let mut output = Vec::new();
+        write_variable_length(&mut output, 0);
+        write_variable_length(&mut output, 0x7F);
+        write_variable_length(&mut output, 0x80);
+        write_variable_length(&mut output, 0x3FFF);
+        assert_eq!(output, [0x00, 0x7F, 0x81, 0x00, 0xFF, 0x7F]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::Result;
+
+// https://vgmrips.net/wiki/VGM_Specification
+const HEADER_SIZE: usize = 0x100;
+const VERSION: u32 = 0x161;
+const SAMPLE_RATE: u64 = 44100;
+const DMG_CLOCK: u64 = 4194304;
+
+const CMD_DMG_WRITE: u8 = 0xB3;
+const CMD_WAIT: u8 = 0x61;
+const CMD_WAIT_NTSC_FRAME: u8 = 0x62;
+const CMD_WAIT_PAL_FRAME: u8 = 0x63;
+const CMD_END: u8 = 0x66;
+const CMD_WAIT_SHORT: u8 = 0x70;
+
+/// Streams Game Boy APU register writes to a VGM file. Writes are timestamped in CPU cycles and
+/// converted to the 44100 Hz VGM time base, the total length is filled in by `finish`.
+pub struct VgmWriter<W: Write + Seek = BufWriter<File>> {
+    output: W,
+    start_cycle: u64,
+    samples: u64,
+}
+
+impl VgmWriter {
+    pub fn create(path: &Path, cycle: u64) -> Result<VgmWriter> {
+        VgmWriter::new(BufWriter::new(File::create(path)?), cycle)
+    }
+}
+
+impl<W: Write + Seek> VgmWriter<W> {
+    /// Starts a log at `cycle`, later writes are timed relative to it.
+    pub fn new(mut output: W, cycle: u64) -> Result<VgmWriter<W>> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+        header[0x08..0x0C].copy_from_slice(&VERSION.to_le_bytes());
+        // Data offset, relative to its own position
+        header[0x34..0x38].copy_from_slice(&(HEADER_SIZE as u32 - 0x34).to_le_bytes());
+        header[0x80..0x84].copy_from_slice(&(DMG_CLOCK as u32).to_le_bytes());
+        output.write_all(&header)?;
+        Ok(VgmWriter {
+            output,
+            start_cycle: cycle,
+            samples: 0,
+        })
+    }
+
+    /// Logs a write of `value` to `address` (FF10-FF3F) at `cycle`.
+    pub fn write_register(&mut self, cycle: u64, address: u16, value: u8) -> Result<()> {
+        debug_assert!((0xFF10..=0xFF3F).contains(&address));
+        self.wait_until(cycle)?;
+        self.output.write_all(&[CMD_DMG_WRITE, (address - 0xFF10) as u8, value])?;
+        Ok(())
+    }
+
+    fn wait_until(&mut self, cycle: u64) -> Result<()> {
+        let target = cycle.saturating_sub(self.start_cycle) * SAMPLE_RATE / DMG_CLOCK;
+        let mut wait = target.saturating_sub(self.samples);
+        while wait > 0 {
+            let samples = wait.min(0xFFFF);
+            match samples {
+                1..=16 => self.output.write_all(&[CMD_WAIT_SHORT + samples as u8 - 1])?,
+                735 => self.output.write_all(&[CMD_WAIT_NTSC_FRAME])?,
+                882 => self.output.write_all(&[CMD_WAIT_PAL_FRAME])?,
+                _ => {
+                    self.output.write_all(&[CMD_WAIT])?;
+                    self.output.write_all(&(samples as u16).to_le_bytes())?;
+                }
+            }
+            wait -= samples;
+        }
+        self.samples = self.samples.max(target);
+        Ok(())
+    }
+
+    /// Ends the log at `cycle`, fills in the header and flushes, returning the underlying writer.
+    pub fn finish(mut self, cycle: u64) -> Result<W> {
+        self.wait_until(cycle)?;
+        self.output.write_all(&[CMD_END])?;
+        let length = self.output.stream_position()? as u32;
+        self.output.seek(SeekFrom::Start(0x04))?;
+        self.output.write_all(&(length - 4).to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(0x18))?;
+        self.output.write_all(&(self.samples as u32).to_le_bytes())?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_are_timed_in_samples() {
+        let mut writer = VgmWriter::new(Cursor::new(Vec::new()), 1000).unwrap();
+        writer.write_register(1000, 0xFF26, 0x80).unwrap();
+        // 4 samples later
+        writer.write_register(1000 + 4 * 95 + 50, 0xFF12, 0xF0).unwrap();
+        // One 60 Hz frame is 735 samples
+        writer.write_register(1000 + 4 * 95 + 50 + 69905, 0xFF14, 0x87).unwrap();
+        let bytes = writer.finish(1000 + 2 * DMG_CLOCK).unwrap().into_inner();
+
+        assert_eq!(&bytes[0..4], b"Vgm ");
+        assert_eq!(
+            u32::from_le_bytes(bytes[0x04..0x08].try_into().unwrap()) as usize,
+            bytes.len() - 4
+        );
+        assert_eq!(u32::from_le_bytes(bytes[0x18..0x1C].try_into().unwrap()), 2 * 44100);
+        assert_eq!(u32::from_le_bytes(bytes[0x80..0x84].try_into().unwrap()), 4194304);
+        let data = &bytes[HEADER_SIZE..];
+        assert_eq!(&data[0..3], &[0xB3, 0x16, 0x80]);
+        assert_eq!(&data[3..7], &[0x73, 0xB3, 0x02, 0xF0]);
+        assert_eq!(&data[7..11], &[0x62, 0xB3, 0x04, 0x87]);
+        assert_eq!(data.last(), Some(&0x66));
+    }
+}
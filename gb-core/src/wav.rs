@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+const HEADER_SIZE: u32 = 44;
+// The RIFF size field counts the data plus the header after it, both have to fit in 32 bits
+const MAX_DATA_SIZE: u32 = u32::MAX - (HEADER_SIZE - 8);
+
+/// Streams 16-bit PCM samples to a WAV file. The sizes in the header are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek = BufWriter<File>> {
+    output: W,
+    channels: u16,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<WavWriter> {
+        WavWriter::new(BufWriter::new(File::create(path)?), channels, sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, channels: u16, sample_rate: u32) -> Result<WavWriter<W>> {
+        let block_align = channels * 2;
+        output.write_all(b"RIFF")?;
+        output.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        output.write_all(b"WAVE")?;
+        output.write_all(b"fmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        output.write_all(&1u16.to_le_bytes())?; // PCM
+        output.write_all(&channels.to_le_bytes())?;
+        output.write_all(&sample_rate.to_le_bytes())?;
+        output.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        output.write_all(&block_align.to_le_bytes())?;
+        output.write_all(&16u16.to_le_bytes())?;
+        output.write_all(b"data")?;
+        output.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter {
+            output,
+            channels,
+            data_size: 0,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Writes interleaved samples in the range -1.0..=1.0, anything outside is clipped.
+    /// Fails once the file would exceed the 4 GiB a WAV header can describe.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let data_size = u32::try_from(samples.len() * 2)
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|&size| size <= MAX_DATA_SIZE)
+            .ok_or_else(|| anyhow!("The WAV file reached its 4 GiB size limit"))?;
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.output.write_all(&value.to_le_bytes())?;
+        }
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    /// Writes a left and a right channel of the same length as stereo frames.
+    pub fn write_stereo(&mut self, left: &[f32], right: &[f32]) -> Result<()> {
+        debug_assert!(self.channels == 2 && left.len() == right.len());
+        let interleaved: Vec<f32> = left.iter().zip(right).flat_map(|(l, r)| [*l, *r]).collect();
+        self.write_samples(&interleaved)
+    }
+
+    /// Fills in the header sizes and flushes, returning the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.output.seek(SeekFrom::Start(4))?;
+        self.output.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.output.write_all(&self.data_size.to_le_bytes())?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes_match_data() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100).unwrap();
+        writer.write_stereo(&[0.0, 1.0, -2.0], &[0.5, -1.0, 0.0]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 12);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 12);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 44100);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 12);
+        // Clipped to the 16-bit range
+        assert_eq!(i16::from_le_bytes([bytes[48], bytes[49]]), i16::MAX);
+        assert_eq!(i16::from_le_bytes([bytes[52], bytes[53]]), -i16::MAX);
+    }
+
+    #[test]
+    fn size_limit_is_an_error() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100).unwrap();
+        writer.data_size = MAX_DATA_SIZE - 4;
+        writer.write_stereo(&[0.0], &[0.0]).unwrap();
+        assert!(writer.write_stereo(&[0.0], &[0.0]).is_err());
+        assert_eq!(writer.data_size, MAX_DATA_SIZE);
+    }
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+# Downloads the expected images published with the screen comparison suites into this directory,
+# named after the ROMs in roms/<suite>/ like `tests/test_roms.rs` expects them.
+# After running it, drop the "expected image not vendored" ignores and mark the real mismatches "fails".
+set -eu
+
+cd "$(dirname "$0")"
+roms=../../../roms
+
+mkdir -p acid mealybug
+curl -fsSL -o acid/dmg-acid2.png https://raw.githubusercontent.com/mattcurrie/dmg-acid2/master/img/reference-dmg.png
+curl -fsSL -o acid/cgb-acid2.png https://raw.githubusercontent.com/mattcurrie/cgb-acid2/master/img/reference.png
+for rom in "$roms"/mealybug/*.gb; do
+    name=$(basename "$rom" .gb)
+    curl -fsSL -o "mealybug/$name.png" \
+        "https://raw.githubusercontent.com/mattcurrie/mealybug-tearoom-tests/master/expected/DMG-blob/$name.png"
+done
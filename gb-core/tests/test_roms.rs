@@ -0,0 +1,396 @@
+//! Runs the bundled test ROMs through the headless runner, the same one behind `gb-debug --headless`.
+//! ROMs the emulator does not pass yet are ignored with the reason, `cargo test -- --ignored` runs them anyway.
+
+use std::path::{Path, PathBuf};
+
+use gb_core::headless::{Completion, HeadlessRunner, Outcome};
+
+// ROM paths are given relative to the workspace root
+fn workspace_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path)
+}
+
+fn run_until(path: &str, frames: u64, completion: Completion) {
+    let mut runner = HeadlessRunner::new(workspace_path(path));
+    let outcome = runner.run(frames, Some(&completion));
+    assert_eq!(outcome, Outcome::Passed, "{}", runner.serial_output());
+}
+
+fn blargg(path: &str) {
+    run_until(path, 4000, Completion::Serial);
+}
+
+fn mooneye(path: &str) {
+    run_until(path, 1200, Completion::Mooneye);
+}
+
+// Compares the frame shown after 5 seconds against `tests/screenshots/<suite>/<rom>.png`, the expected image
+// published with the test suite (`tests/screenshots/fetch.sh` downloads them). Never use this emulator's own output as a reference, it would turn rendering
+// bugs into expected results.
+// On a mismatch a diff image is written to the target directory, see the test output for its path.
+fn screen(path: &str) {
+    let rom = Path::new(path);
+    let name = format!("{}.png", rom.file_stem().unwrap().to_string_lossy());
+    let suite = rom.parent().unwrap().file_name().unwrap().to_string_lossy();
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/screenshots")
+        .join(suite.as_ref())
+        .join(&name);
+    assert!(
+        reference.exists(),
+        "{} is missing, copy the expected image of the test suite there",
+        reference.display()
+    );
+
+    let diff = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-{}", suite, name));
+    let mut runner = HeadlessRunner::new(workspace_path(path));
+    assert_eq!(runner.run(300, None), Outcome::Passed);
+    assert_eq!(runner.compare_screen(&reference, Some(&diff)).unwrap(), Outcome::Passed);
+}
+
+macro_rules! rom_tests {
+    ($check:ident, { $($(#[$attr:meta])* $name:ident: $path:expr,)* }) => {
+        $(
+            $(#[$attr])*
+            #[test]
+            fn $name() {
+                $check($path);
+            }
+        )*
+    };
+}
+
+mod blargg {
+    use super::*;
+
+    rom_tests!(blargg, {
+        #[ignore = "fails"]
+        test_01_read_timing: "roms/blargg/01-read_timing.gb",
+        test_01_special: "roms/blargg/01-special.gb",
+        test_02_interrupts: "roms/blargg/02-interrupts.gb",
+        #[ignore = "fails"]
+        test_02_write_timing: "roms/blargg/02-write_timing.gb",
+        #[ignore = "fails"]
+        test_03_modify_timing: "roms/blargg/03-modify_timing.gb",
+        test_03_op_sp_hl: "roms/blargg/03-op sp,hl.gb",
+        test_04_op_r_imm: "roms/blargg/04-op r,imm.gb",
+        test_05_op_rp: "roms/blargg/05-op rp.gb",
+        test_06_ld_r_r: "roms/blargg/06-ld r,r.gb",
+        test_07_jr_jp_call_ret_rst: "roms/blargg/07-jr,jp,call,ret,rst.gb",
+        test_08_misc_instrs: "roms/blargg/08-misc instrs.gb",
+        test_09_op_r_r: "roms/blargg/09-op r,r.gb",
+        test_10_bit_ops: "roms/blargg/10-bit ops.gb",
+        test_11_op_a_hl: "roms/blargg/11-op a,(hl).gb",
+        cpu_instrs: "roms/blargg/cpu_instrs.gb",
+        #[ignore = "fails"]
+        instr_timing: "roms/blargg/instr_timing.gb",
+        #[ignore = "no result within the frame limit"]
+        interrupt_time: "roms/blargg/interrupt_time.gb",
+        #[ignore = "fails"]
+        mem_timing: "roms/blargg/mem_timing.gb",
+    });
+}
+
+mod mooneye {
+    use super::*;
+
+    rom_tests!(mooneye, {
+        #[ignore = "fails"]
+        acceptance_add_sp_e_timing: "roms/mooneye/acceptance/add_sp_e_timing.gb",
+        acceptance_bits_mem_oam: "roms/mooneye/acceptance/bits/mem_oam.gb",
+        acceptance_bits_reg_f: "roms/mooneye/acceptance/bits/reg_f.gb",
+        #[ignore = "fails"]
+        acceptance_bits_unused_hwio_gs: "roms/mooneye/acceptance/bits/unused_hwio-GS.gb",
+        #[ignore = "fails"]
+        acceptance_boot_div_s: "roms/mooneye/acceptance/boot_div-S.gb",
+        #[ignore = "fails"]
+        acceptance_boot_div_dmg0: "roms/mooneye/acceptance/boot_div-dmg0.gb",
+        #[ignore = "fails"]
+        acceptance_boot_div_dmgabcmgb: "roms/mooneye/acceptance/boot_div-dmgABCmgb.gb",
+        #[ignore = "fails"]
+        acceptance_boot_div2_s: "roms/mooneye/acceptance/boot_div2-S.gb",
+        #[ignore = "fails"]
+        acceptance_boot_hwio_s: "roms/mooneye/acceptance/boot_hwio-S.gb",
+        #[ignore = "fails"]
+        acceptance_boot_hwio_dmg0: "roms/mooneye/acceptance/boot_hwio-dmg0.gb",
+        #[ignore = "fails"]
+        acceptance_boot_hwio_dmgabcmgb: "roms/mooneye/acceptance/boot_hwio-dmgABCmgb.gb",
+        #[ignore = "fails"]
+        acceptance_boot_regs_dmg0: "roms/mooneye/acceptance/boot_regs-dmg0.gb",
+        acceptance_boot_regs_dmgabc: "roms/mooneye/acceptance/boot_regs-dmgABC.gb",
+        #[ignore = "fails"]
+        acceptance_boot_regs_mgb: "roms/mooneye/acceptance/boot_regs-mgb.gb",
+        #[ignore = "fails"]
+        acceptance_boot_regs_sgb: "roms/mooneye/acceptance/boot_regs-sgb.gb",
+        #[ignore = "fails"]
+        acceptance_boot_regs_sgb2: "roms/mooneye/acceptance/boot_regs-sgb2.gb",
+        #[ignore = "fails"]
+        acceptance_call_cc_timing: "roms/mooneye/acceptance/call_cc_timing.gb",
+        #[ignore = "fails"]
+        acceptance_call_cc_timing2: "roms/mooneye/acceptance/call_cc_timing2.gb",
+        #[ignore = "fails"]
+        acceptance_call_timing: "roms/mooneye/acceptance/call_timing.gb",
+        #[ignore = "fails"]
+        acceptance_call_timing2: "roms/mooneye/acceptance/call_timing2.gb",
+        #[ignore = "fails"]
+        acceptance_di_timing_gs: "roms/mooneye/acceptance/di_timing-GS.gb",
+        #[ignore = "fails"]
+        acceptance_div_timing: "roms/mooneye/acceptance/div_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ei_sequence: "roms/mooneye/acceptance/ei_sequence.gb",
+        #[ignore = "fails"]
+        acceptance_ei_timing: "roms/mooneye/acceptance/ei_timing.gb",
+        acceptance_halt_ime0_ei: "roms/mooneye/acceptance/halt_ime0_ei.gb",
+        #[ignore = "fails"]
+        acceptance_halt_ime0_nointr_timing: "roms/mooneye/acceptance/halt_ime0_nointr_timing.gb",
+        acceptance_halt_ime1_timing: "roms/mooneye/acceptance/halt_ime1_timing.gb",
+        #[ignore = "fails"]
+        acceptance_halt_ime1_timing2_gs: "roms/mooneye/acceptance/halt_ime1_timing2-GS.gb",
+        #[ignore = "fails"]
+        acceptance_if_ie_registers: "roms/mooneye/acceptance/if_ie_registers.gb",
+        acceptance_instr_daa: "roms/mooneye/acceptance/instr/daa.gb",
+        #[ignore = "fails"]
+        acceptance_interrupts_ie_push: "roms/mooneye/acceptance/interrupts/ie_push.gb",
+        #[ignore = "fails"]
+        acceptance_intr_timing: "roms/mooneye/acceptance/intr_timing.gb",
+        #[ignore = "fails"]
+        acceptance_jp_cc_timing: "roms/mooneye/acceptance/jp_cc_timing.gb",
+        #[ignore = "fails"]
+        acceptance_jp_timing: "roms/mooneye/acceptance/jp_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ld_hl_sp_e_timing: "roms/mooneye/acceptance/ld_hl_sp_e_timing.gb",
+        acceptance_oam_dma_basic: "roms/mooneye/acceptance/oam_dma/basic.gb",
+        #[ignore = "fails"]
+        acceptance_oam_dma_reg_read: "roms/mooneye/acceptance/oam_dma/reg_read.gb",
+        #[ignore = "unsupported MBC type"]
+        acceptance_oam_dma_sources_gs: "roms/mooneye/acceptance/oam_dma/sources-GS.gb",
+        #[ignore = "fails"]
+        acceptance_oam_dma_restart: "roms/mooneye/acceptance/oam_dma_restart.gb",
+        #[ignore = "fails"]
+        acceptance_oam_dma_start: "roms/mooneye/acceptance/oam_dma_start.gb",
+        #[ignore = "fails"]
+        acceptance_oam_dma_timing: "roms/mooneye/acceptance/oam_dma_timing.gb",
+        #[ignore = "fails"]
+        acceptance_pop_timing: "roms/mooneye/acceptance/pop_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_hblank_ly_scx_timing_gs: "roms/mooneye/acceptance/ppu/hblank_ly_scx_timing-GS.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_1_2_timing_gs: "roms/mooneye/acceptance/ppu/intr_1_2_timing-GS.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_2_0_timing: "roms/mooneye/acceptance/ppu/intr_2_0_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_2_mode0_timing: "roms/mooneye/acceptance/ppu/intr_2_mode0_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_2_mode0_timing_sprites: "roms/mooneye/acceptance/ppu/intr_2_mode0_timing_sprites.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_2_mode3_timing: "roms/mooneye/acceptance/ppu/intr_2_mode3_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_intr_2_oam_ok_timing: "roms/mooneye/acceptance/ppu/intr_2_oam_ok_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_lcdon_timing_gs: "roms/mooneye/acceptance/ppu/lcdon_timing-GS.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_lcdon_write_timing_gs: "roms/mooneye/acceptance/ppu/lcdon_write_timing-GS.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_stat_irq_blocking: "roms/mooneye/acceptance/ppu/stat_irq_blocking.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_stat_lyc_onoff: "roms/mooneye/acceptance/ppu/stat_lyc_onoff.gb",
+        #[ignore = "fails"]
+        acceptance_ppu_vblank_stat_intr_gs: "roms/mooneye/acceptance/ppu/vblank_stat_intr-GS.gb",
+        #[ignore = "fails"]
+        acceptance_push_timing: "roms/mooneye/acceptance/push_timing.gb",
+        #[ignore = "fails"]
+        acceptance_rapid_di_ei: "roms/mooneye/acceptance/rapid_di_ei.gb",
+        #[ignore = "fails"]
+        acceptance_ret_cc_timing: "roms/mooneye/acceptance/ret_cc_timing.gb",
+        #[ignore = "fails"]
+        acceptance_ret_timing: "roms/mooneye/acceptance/ret_timing.gb",
+        #[ignore = "fails"]
+        acceptance_reti_intr_timing: "roms/mooneye/acceptance/reti_intr_timing.gb",
+        #[ignore = "fails"]
+        acceptance_reti_timing: "roms/mooneye/acceptance/reti_timing.gb",
+        #[ignore = "fails"]
+        acceptance_rst_timing: "roms/mooneye/acceptance/rst_timing.gb",
+        #[ignore = "fails"]
+        acceptance_serial_boot_sclk_align_dmgabcmgb: "roms/mooneye/acceptance/serial/boot_sclk_align-dmgABCmgb.gb",
+        #[ignore = "fails"]
+        acceptance_timer_div_write: "roms/mooneye/acceptance/timer/div_write.gb",
+        #[ignore = "fails"]
+        acceptance_timer_rapid_toggle: "roms/mooneye/acceptance/timer/rapid_toggle.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tim00: "roms/mooneye/acceptance/timer/tim00.gb",
+        acceptance_timer_tim00_div_trigger: "roms/mooneye/acceptance/timer/tim00_div_trigger.gb",
+        acceptance_timer_tim01: "roms/mooneye/acceptance/timer/tim01.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tim01_div_trigger: "roms/mooneye/acceptance/timer/tim01_div_trigger.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tim10: "roms/mooneye/acceptance/timer/tim10.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tim10_div_trigger: "roms/mooneye/acceptance/timer/tim10_div_trigger.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tim11: "roms/mooneye/acceptance/timer/tim11.gb",
+        acceptance_timer_tim11_div_trigger: "roms/mooneye/acceptance/timer/tim11_div_trigger.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tima_reload: "roms/mooneye/acceptance/timer/tima_reload.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tima_write_reloading: "roms/mooneye/acceptance/timer/tima_write_reloading.gb",
+        #[ignore = "fails"]
+        acceptance_timer_tma_write_reloading: "roms/mooneye/acceptance/timer/tma_write_reloading.gb",
+        emulator_only_mbc1_bits_bank1: "roms/mooneye/emulator-only/mbc1/bits_bank1.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_bits_bank2: "roms/mooneye/emulator-only/mbc1/bits_bank2.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_bits_mode: "roms/mooneye/emulator-only/mbc1/bits_mode.gb",
+        emulator_only_mbc1_bits_ramg: "roms/mooneye/emulator-only/mbc1/bits_ramg.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_multicart_rom_8mb: "roms/mooneye/emulator-only/mbc1/multicart_rom_8Mb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_ram_256kb: "roms/mooneye/emulator-only/mbc1/ram_256kb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_ram_64kb: "roms/mooneye/emulator-only/mbc1/ram_64kb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_16mb: "roms/mooneye/emulator-only/mbc1/rom_16Mb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_1mb: "roms/mooneye/emulator-only/mbc1/rom_1Mb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_2mb: "roms/mooneye/emulator-only/mbc1/rom_2Mb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_4mb: "roms/mooneye/emulator-only/mbc1/rom_4Mb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_512kb: "roms/mooneye/emulator-only/mbc1/rom_512kb.gb",
+        #[ignore = "panics on an out of range WRAM bank"]
+        emulator_only_mbc1_rom_8mb: "roms/mooneye/emulator-only/mbc1/rom_8Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_bits_ramg: "roms/mooneye/emulator-only/mbc2/bits_ramg.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_bits_romb: "roms/mooneye/emulator-only/mbc2/bits_romb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_bits_unused: "roms/mooneye/emulator-only/mbc2/bits_unused.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_ram: "roms/mooneye/emulator-only/mbc2/ram.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_rom_1mb: "roms/mooneye/emulator-only/mbc2/rom_1Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_rom_2mb: "roms/mooneye/emulator-only/mbc2/rom_2Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc2_rom_512kb: "roms/mooneye/emulator-only/mbc2/rom_512kb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_16mb: "roms/mooneye/emulator-only/mbc5/rom_16Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_1mb: "roms/mooneye/emulator-only/mbc5/rom_1Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_2mb: "roms/mooneye/emulator-only/mbc5/rom_2Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_4mb: "roms/mooneye/emulator-only/mbc5/rom_4Mb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_512kb: "roms/mooneye/emulator-only/mbc5/rom_512kb.gb",
+        #[ignore = "unsupported MBC type"]
+        emulator_only_mbc5_rom_8mb: "roms/mooneye/emulator-only/mbc5/rom_8Mb.gb",
+        #[ignore = "no result within the frame limit"]
+        madness_mgb_oam_dma_halt_sprites: "roms/mooneye/madness/mgb_oam_dma_halt_sprites.gb",
+        #[ignore = "needs manual inspection of the screen"]
+        manual_only_sprite_priority: "roms/mooneye/manual-only/sprite_priority.gb",
+        #[ignore = "fails"]
+        misc_bits_unused_hwio_c: "roms/mooneye/misc/bits/unused_hwio-C.gb",
+        #[ignore = "fails"]
+        misc_boot_div_a: "roms/mooneye/misc/boot_div-A.gb",
+        #[ignore = "fails"]
+        misc_boot_div_cgb0: "roms/mooneye/misc/boot_div-cgb0.gb",
+        #[ignore = "fails"]
+        misc_boot_div_cgbabcde: "roms/mooneye/misc/boot_div-cgbABCDE.gb",
+        #[ignore = "fails"]
+        misc_boot_hwio_c: "roms/mooneye/misc/boot_hwio-C.gb",
+        #[ignore = "fails"]
+        misc_boot_regs_a: "roms/mooneye/misc/boot_regs-A.gb",
+        #[ignore = "fails"]
+        misc_boot_regs_cgb: "roms/mooneye/misc/boot_regs-cgb.gb",
+        #[ignore = "fails"]
+        misc_ppu_vblank_stat_intr_c: "roms/mooneye/misc/ppu/vblank_stat_intr-C.gb",
+        #[ignore = "utility ROM, not a pass/fail test"]
+        utils_bootrom_dumper: "roms/mooneye/utils/bootrom_dumper.gb",
+        utils_dump_boot_hwio: "roms/mooneye/utils/dump_boot_hwio.gb",
+    });
+}
+
+// Expected images: `expected/DMG-blob/<rom>.png` of https://github.com/mattcurrie/mealybug-tearoom-tests.
+// Once an image is vendored, drop its ignore, or change it to "fails" if the output does not match yet.
+mod mealybug {
+    use super::*;
+
+    rom_tests!(screen, {
+        #[ignore = "expected image not vendored"]
+        m2_win_en_toggle: "roms/mealybug/m2_win_en_toggle.gb",
+        #[ignore = "expected image not vendored"]
+        m3_bgp_change: "roms/mealybug/m3_bgp_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_bgp_change_sprites: "roms/mealybug/m3_bgp_change_sprites.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_bg_en_change: "roms/mealybug/m3_lcdc_bg_en_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_bg_en_change2: "roms/mealybug/m3_lcdc_bg_en_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_bg_map_change: "roms/mealybug/m3_lcdc_bg_map_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_bg_map_change2: "roms/mealybug/m3_lcdc_bg_map_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_obj_en_change: "roms/mealybug/m3_lcdc_obj_en_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_obj_en_change_variant: "roms/mealybug/m3_lcdc_obj_en_change_variant.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_obj_size_change: "roms/mealybug/m3_lcdc_obj_size_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_obj_size_change_scx: "roms/mealybug/m3_lcdc_obj_size_change_scx.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_tile_sel_change: "roms/mealybug/m3_lcdc_tile_sel_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_tile_sel_change2: "roms/mealybug/m3_lcdc_tile_sel_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_tile_sel_win_change: "roms/mealybug/m3_lcdc_tile_sel_win_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_tile_sel_win_change2: "roms/mealybug/m3_lcdc_tile_sel_win_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_win_en_change_multiple: "roms/mealybug/m3_lcdc_win_en_change_multiple.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_win_en_change_multiple_wx: "roms/mealybug/m3_lcdc_win_en_change_multiple_wx.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_win_map_change: "roms/mealybug/m3_lcdc_win_map_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_lcdc_win_map_change2: "roms/mealybug/m3_lcdc_win_map_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_obp0_change: "roms/mealybug/m3_obp0_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_scx_high_5_bits: "roms/mealybug/m3_scx_high_5_bits.gb",
+        #[ignore = "expected image not vendored"]
+        m3_scx_high_5_bits_change2: "roms/mealybug/m3_scx_high_5_bits_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_scx_low_3_bits: "roms/mealybug/m3_scx_low_3_bits.gb",
+        #[ignore = "expected image not vendored"]
+        m3_scy_change: "roms/mealybug/m3_scy_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_scy_change2: "roms/mealybug/m3_scy_change2.gb",
+        #[ignore = "expected image not vendored"]
+        m3_window_timing: "roms/mealybug/m3_window_timing.gb",
+        #[ignore = "expected image not vendored"]
+        m3_window_timing_wx_0: "roms/mealybug/m3_window_timing_wx_0.gb",
+        #[ignore = "expected image not vendored"]
+        m3_wx_4_change: "roms/mealybug/m3_wx_4_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_wx_4_change_sprites: "roms/mealybug/m3_wx_4_change_sprites.gb",
+        #[ignore = "expected image not vendored"]
+        m3_wx_5_change: "roms/mealybug/m3_wx_5_change.gb",
+        #[ignore = "expected image not vendored"]
+        m3_wx_6_change: "roms/mealybug/m3_wx_6_change.gb",
+    });
+}
+
+// Expected images: `img/reference.png` of https://github.com/mattcurrie/cgb-acid2 and `img/reference-dmg.png`
+// of https://github.com/mattcurrie/dmg-acid2, renamed after the ROM.
+mod acid {
+    use super::*;
+
+    rom_tests!(screen, {
+        #[ignore = "expected image not vendored"]
+        cgb_acid2: "roms/acid/cgb-acid2.gbc",
+        #[ignore = "expected image not vendored"]
+        dmg_acid2: "roms/acid/dmg-acid2.gb",
+    });
+}
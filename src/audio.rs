@@ -1,13 +1,162 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use cpal::traits::{HostTrait, DeviceTrait, StreamTrait};
 use cpal::{Sample, FromSample};
 
-use crate::io::sound::AudioPlayer;
+use anyhow::Result;
+use gb_core::io::sound::{AudioPlayer, NullPlayer, RecordingPlayer};
+use gb_core::wav::WavWriter;
+
+/// Where the emulated audio goes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AudioBackend {
+    // The default output device through cpal
+    Device,
+    // Discards all samples
+    Silent,
+    // Keeps all samples in memory
+    Recording,
+}
+
+impl AudioBackend {
+    pub const ALL: [AudioBackend; 3] = [AudioBackend::Device, AudioBackend::Silent, AudioBackend::Recording];
+
+    /// Parses the `--audio` command line value.
+    pub fn parse(text: &str) -> Option<AudioBackend> {
+        match text {
+            "device" => Some(AudioBackend::Device),
+            "silent" => Some(AudioBackend::Silent),
+            "recording" => Some(AudioBackend::Recording),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioBackend::Device => "Output Device",
+            AudioBackend::Silent => "Silent",
+            AudioBackend::Recording => "Record to Memory",
+        }
+    }
+}
+
+/// How the game window paces emulation.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyncMode {
+    // Runs as many cycles as the time since the last UI frame
+    Video,
+    // Runs until the audio device buffer reaches the target latency, falls back to Video without a device
+    Audio,
+}
+
+impl SyncMode {
+    pub const ALL: [SyncMode; 2] = [SyncMode::Video, SyncMode::Audio];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncMode::Video => "Sync to Video",
+            SyncMode::Audio => "Sync to Audio",
+        }
+    }
+}
+
+// Largest change to the output rate, small enough to not be heard as a pitch change
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+/// Dynamic rate control: nudges the output sample rate so the device buffer stays around the target latency,
+/// instead of slowly running dry or overflowing because the emulated and the device clocks differ.
+pub struct RateControl {
+    pub target_latency_ms: f32,
+}
+
+impl RateControl {
+    pub fn new() -> Self {
+        Self { target_latency_ms: 100.0 }
+    }
+
+    pub fn target_samples(&self, sample_rate: u32) -> usize {
+        (self.target_latency_ms / 1000.0 * sample_rate as f32) as usize
+    }
+
+    /// The output rate multiplier for a buffer holding `buffered` samples.
+    pub fn ratio(&self, buffered: usize, sample_rate: u32) -> f64 {
+        let target = self.target_samples(sample_rate).max(1) as f64;
+        let error = ((target - buffered as f64) / target).clamp(-1.0, 1.0);
+        1.0 + error * MAX_RATE_ADJUSTMENT
+    }
+}
+
+/// Counters updated by the audio thread.
+#[derive(Default)]
+pub struct AudioCounters {
+    // Times the device asked for more samples than were buffered
+    pub underflows: AtomicU64,
+    // Samples thrown away because the buffer was full
+    pub dropped_samples: AtomicU64,
+    // Set while the buffer is empty, so a pause counts as one underflow
+    starved: AtomicBool,
+}
+
+pub struct AudioOutput {
+    pub player: Box<dyn AudioPlayer>,
+    // Must be kept alive for as long as the device should play
+    pub stream: Option<cpal::Stream>,
+    pub recording: Option<Arc<Mutex<VecDeque<(f32, f32)>>>>,
+    pub counters: Option<Arc<AudioCounters>>,
+    // Set when the requested backend was not available
+    pub warning: Option<String>,
+}
+
+impl AudioOutput {
+    /// Opens `backend`, falling back to a silent player when there is no usable output device.
+    pub fn open(backend: AudioBackend) -> AudioOutput {
+        let mut output = AudioOutput {
+            player: Box::new(NullPlayer::new()),
+            stream: None,
+            recording: None,
+            counters: None,
+            warning: None,
+        };
+        match backend {
+            AudioBackend::Device => match CpalPlayer::get() {
+                Some((player, stream)) => {
+                    output.counters = Some(player.counters.clone());
+                    output.player = Box::new(player);
+                    output.stream = Some(stream);
+                }
+                None => {
+                    let warning = "No usable audio output device found, audio is disabled";
+                    eprintln!("Warning: {}", warning);
+                    output.warning = Some(warning.to_string());
+                }
+            },
+            AudioBackend::Silent => {}
+            AudioBackend::Recording => {
+                let player = RecordingPlayer::new(44100);
+                output.recording = Some(player.samples());
+                output.player = Box::new(player);
+            }
+        }
+        output
+    }
+}
+
+/// Saves the samples kept by the Recording backend as a 16-bit stereo WAV file.
+pub fn save_recording(path: &Path, samples: &VecDeque<(f32, f32)>) -> Result<()> {
+    let mut writer = WavWriter::create(path, 2, 44100)?;
+    let interleaved: Vec<f32> = samples.iter().flat_map(|(left, right)| [*left, *right]).collect();
+    writer.write_samples(&interleaved)?;
+    writer.finish()?;
+    Ok(())
+}
 
 
 pub struct CpalPlayer {
     buffer: Arc<Mutex<Vec<(f32, f32)>>>,
     sample_rate: u32,
+    counters: Arc<AudioCounters>,
 }
 
 impl CpalPlayer {
@@ -52,23 +201,26 @@ impl CpalPlayer {
 
         let shared_buffer = Arc::new(Mutex::new(Vec::new()));
         let stream_buffer = shared_buffer.clone();
+        let counters = Arc::new(AudioCounters::default());
+        let stream_counters = counters.clone();
 
         let player = CpalPlayer {
             buffer: shared_buffer,
             sample_rate: config.sample_rate.0,
+            counters,
         };
 
         let stream = match sample_format {
-            cpal::SampleFormat::I8 => device.build_output_stream(&config, move|data: &mut [i8], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::I16 => device.build_output_stream(&config, move|data: &mut [i16], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::I32 => device.build_output_stream(&config, move|data: &mut [i32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::I64 => device.build_output_stream(&config, move|data: &mut [i64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::U8 => device.build_output_stream(&config, move|data: &mut [u8], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::U16 => device.build_output_stream(&config, move|data: &mut [u16], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::U32 => device.build_output_stream(&config, move|data: &mut [u32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::U64 => device.build_output_stream(&config, move|data: &mut [u64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::F32 => device.build_output_stream(&config, move|data: &mut [f32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
-            cpal::SampleFormat::F64 => device.build_output_stream(&config, move|data: &mut [f64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer), err_fn, None),
+            cpal::SampleFormat::I8 => device.build_output_stream(&config, move|data: &mut [i8], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::I16 => device.build_output_stream(&config, move|data: &mut [i16], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::I32 => device.build_output_stream(&config, move|data: &mut [i32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::I64 => device.build_output_stream(&config, move|data: &mut [i64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::U8 => device.build_output_stream(&config, move|data: &mut [u8], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::U16 => device.build_output_stream(&config, move|data: &mut [u16], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::U32 => device.build_output_stream(&config, move|data: &mut [u32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::U64 => device.build_output_stream(&config, move|data: &mut [u64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::F32 => device.build_output_stream(&config, move|data: &mut [f32], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
+            cpal::SampleFormat::F64 => device.build_output_stream(&config, move|data: &mut [f64], _callback_info: &cpal::OutputCallbackInfo| cpal_thread(data, &stream_buffer, &stream_counters), err_fn, None),
             sf => panic!("Unsupported sample format {}", sf),
         }.unwrap();
 
@@ -78,13 +230,20 @@ impl CpalPlayer {
     }
 }
 
-fn cpal_thread<T: Sample + FromSample<f32>>(outbuffer: &mut[T], audio_buffer: &Arc<Mutex<Vec<(f32, f32)>>>) {
+fn cpal_thread<T: Sample + FromSample<f32>>(outbuffer: &mut[T], audio_buffer: &Arc<Mutex<Vec<(f32, f32)>>>, counters: &AudioCounters) {
     let mut inbuffer = audio_buffer.lock().unwrap();
     let outlen =  ::std::cmp::min(outbuffer.len() / 2, inbuffer.len());
     for (i, (in_l, in_r)) in inbuffer.drain(..outlen).enumerate() {
         outbuffer[i*2] = T::from_sample(in_l);
         outbuffer[i*2+1] = T::from_sample(in_r);
     }
+    // Play silence for the missing samples instead of whatever the device buffer held
+    outbuffer[outlen * 2..].fill(T::EQUILIBRIUM);
+    let starved = outlen * 2 < outbuffer.len();
+    if starved && !counters.starved.load(Ordering::Relaxed) {
+        counters.underflows.fetch_add(1, Ordering::Relaxed);
+    }
+    counters.starved.store(starved, Ordering::Relaxed);
 }
 
 impl AudioPlayer for CpalPlayer {
@@ -93,10 +252,11 @@ impl AudioPlayer for CpalPlayer {
 
         let mut buffer = self.buffer.lock().unwrap();
 
-        for (l, r) in buf_left.iter().zip(buf_right) {
+        for (i, (l, r)) in buf_left.iter().zip(buf_right).enumerate() {
             if buffer.len() > self.sample_rate as usize {
                 // Do not fill the buffer with more than 1 second of data
                 // This speeds up the resync after the turning on and off the speed limiter
+                self.counters.dropped_samples.fetch_add((buf_left.len() - i) as u64, Ordering::Relaxed);
                 return
             }
             buffer.push((*l, *r));
@@ -110,5 +270,13 @@ impl AudioPlayer for CpalPlayer {
     fn underflowed(&self) -> bool {
         (*self.buffer.lock().unwrap()).len() == 0
     }
+
+    fn clear_buffer(&mut self) {
+        self.buffer.lock().unwrap().clear();
+    }
+
+    fn buffered_samples(&self) -> Option<usize> {
+        Some(self.buffer.lock().unwrap().len())
+    }
 }
 
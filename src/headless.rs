@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use gb_core::headless::{Completion, HeadlessRunner, Outcome};
+use gb_core::tracker::MusicLog;
+
+/// Entry point for `--headless <rom> [--frames <n>] [--until mooneye|serial|hash:<hex>] [--screenshot <png>]
+/// [--reference <png> [--diff <png>]] [--wav <path>] [--stems <path>] [--mute <channels>] [--solo <channels>]
+/// [--midi <path>] [--vgm <path>] [--song <n>]`.
+/// Channels are given as a comma separated list of 1 to 4, stems are written next to `path` as "<name>-chN.wav".
+/// `--song` picks the song of a GBS file, counting from 1.
+/// Prints the outcome and returns the process exit code, 0 when passed.
+pub fn main(args: &[String]) -> i32 {
+    match run_from_args(args) {
+        Ok(Outcome::Passed) => {
+            println!("Passed");
+            0
+        }
+        Ok(Outcome::Failed(reason)) => {
+            println!("Failed: {}", reason);
+            1
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            2
+        }
+    }
+}
+
+fn run_from_args(args: &[String]) -> Result<Outcome> {
+    let mut rom_path = None;
+    let mut frames = 60 * 60;
+    let mut completion = None;
+    let mut screenshot_path = None;
+    let mut reference_path = None;
+    let mut diff_path = None;
+    let mut wav_path = None;
+    let mut stems_path = None;
+    let mut midi_path = None;
+    let mut vgm_path = None;
+    let mut muted = [false; 4];
+    let mut soloed = [false; 4];
+    let mut song = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let value = args.next().ok_or(anyhow!("--frames needs a value"))?;
+                frames = value.parse()?;
+            }
+            "--until" => {
+                let value = args.next().ok_or(anyhow!("--until needs a value"))?;
+                completion = Some(Completion::parse(value)?);
+            }
+            "--screenshot" => {
+                let value = args.next().ok_or(anyhow!("--screenshot needs a value"))?;
+                screenshot_path = Some(PathBuf::from(value));
+            }
+            "--reference" => {
+                let value = args.next().ok_or(anyhow!("--reference needs a value"))?;
+                reference_path = Some(PathBuf::from(value));
+            }
+            "--diff" => {
+                let value = args.next().ok_or(anyhow!("--diff needs a value"))?;
+                diff_path = Some(PathBuf::from(value));
+            }
+            "--wav" => {
+                let value = args.next().ok_or(anyhow!("--wav needs a value"))?;
+                wav_path = Some(PathBuf::from(value));
+            }
+            "--stems" => {
+                let value = args.next().ok_or(anyhow!("--stems needs a value"))?;
+                stems_path = Some(PathBuf::from(value));
+            }
+            "--midi" => {
+                let value = args.next().ok_or(anyhow!("--midi needs a value"))?;
+                midi_path = Some(PathBuf::from(value));
+            }
+            "--vgm" => {
+                let value = args.next().ok_or(anyhow!("--vgm needs a value"))?;
+                vgm_path = Some(PathBuf::from(value));
+            }
+            "--mute" => {
+                let value = args.next().ok_or(anyhow!("--mute needs a value"))?;
+                muted = parse_channels(value)?;
+            }
+            "--solo" => {
+                let value = args.next().ok_or(anyhow!("--solo needs a value"))?;
+                soloed = parse_channels(value)?;
+            }
+            "--song" => {
+                let value = args.next().ok_or(anyhow!("--song needs a value"))?;
+                match value.parse::<u8>() {
+                    Ok(n @ 1..) => song = Some(n - 1),
+                    _ => return Err(anyhow!("Invalid song: {}, songs count from 1", value)),
+                }
+            }
+            _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+            _ => return Err(anyhow!("Unexpected argument: {}", arg)),
+        }
+    }
+    let rom_path = rom_path.ok_or(anyhow!("No ROM given"))?;
+    if !rom_path.is_file() {
+        return Err(anyhow!("ROM not found: {}", rom_path.display()));
+    }
+
+    let mut runner = HeadlessRunner::new(rom_path);
+    if let Some(song) = song {
+        if runner.gameboy.gbs().is_none() {
+            return Err(anyhow!("--song needs a GBS file"));
+        }
+        runner.gameboy.play_song(song);
+    }
+    let sound = &mut runner.gameboy.cpu.mmu.sound;
+    sound.muted = muted;
+    sound.soloed = soloed;
+    if let Some(wav_path) = &wav_path {
+        sound.start_wav_recording(wav_path)?;
+    }
+    if let Some(stems_path) = &stems_path {
+        sound.start_stem_recording(stems_path)?;
+    }
+    if midi_path.is_some() {
+        sound.music_log = Some(MusicLog::new());
+    }
+    if let Some(vgm_path) = &vgm_path {
+        sound.start_vgm_recording(vgm_path)?;
+    }
+    let mut outcome = runner.run(frames, completion.as_ref());
+    let sound = &mut runner.gameboy.cpu.mmu.sound;
+    sound.stop_wav_recording()?;
+    sound.stop_stem_recording()?;
+    sound.stop_vgm_recording()?;
+    if let Some(e) = sound.take_recording_error() {
+        return Err(e);
+    }
+    if let (Some(midi_path), Some(log)) = (&midi_path, &sound.music_log) {
+        log.save_midi(midi_path)?;
+    }
+    println!("Frames: {}", runner.gameboy.frame_count());
+    println!("Screen hash: {:016x}", runner.screen_hash());
+    let serial = runner.serial_output();
+    if !serial.is_empty() {
+        println!("Serial output:\n{}", serial.trim_end());
+    }
+
+    if let Some(screenshot_path) = screenshot_path {
+        runner.screenshot().save_png(&screenshot_path)?;
+    }
+    if let (Outcome::Passed, Some(reference_path)) = (&outcome, reference_path) {
+        outcome = runner.compare_screen(&reference_path, diff_path.as_deref())?;
+    }
+    Ok(outcome)
+}
+
+// Parses a list like "1,3" into flags per channel
+fn parse_channels(text: &str) -> Result<[bool; 4]> {
+    let mut channels = [false; 4];
+    for channel in text.split(',') {
+        match channel.trim().parse::<usize>() {
+            Ok(channel @ 1..=4) => channels[channel - 1] = true,
+            _ => return Err(anyhow!("Invalid channel: {}, expected 1 to 4", channel)),
+        }
+    }
+    Ok(channels)
+}
@@ -1,30 +1,25 @@
-use crate::cartridge::Cartridge;
-use crate::cpu::CPU;
+use gb_core::cartridge::Cartridge;
 use crate::ui::windows::{
-    Breakpoints, Disassembly, GameWindow, MemoryView, Registers, TileMapViewer,
+    ApuViewer, Audio, Breakpoints, Disassembly, GameWindow, Log, MemoryView, Registers, TileMapViewer, Tracker,
 };
 use crate::ui::{Pane, TreeManager};
-use audio::CpalPlayer;
+use audio::AudioBackend;
 use eframe::egui;
 use eframe::epaint::Color32;
-use egui::{CentralPanel, Stroke, TopBottomPanel, Widget};
+use egui::{Button, CentralPanel, DragValue, Stroke, TopBottomPanel, Widget};
 use egui_tiles::{Container, Linear, LinearDir, Tile, Tiles};
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::Read;
 use std::ops::BitAndAssign;
-use std::path::PathBuf;
-use std::sync::Arc;
-use crate::io::sound::AudioPlayer;
-
-mod assembler;
-mod cartridge;
-mod cpu;
-mod disassembler;
-mod gbmode;
-mod io;
-mod mbc;
-mod mmu;
-mod ppu;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use gb_core::movie::MovieMode;
+use gb_core::screenshot::Screenshot;
+use gb_core::symbols::SymbolTable;
+use gb_core::GameBoy;
+
+mod headless;
 mod ui;
 mod audio;
 
@@ -38,6 +33,36 @@ pub fn bit(condition: bool) -> u8 {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--headless") {
+        std::process::exit(headless::main(&args[1..]));
+    }
+
+    // gb-debug [--audio device|silent|recording] [--wav <path>] [rom]
+    let mut rom_path = None;
+    let mut audio_backend = AudioBackend::Device;
+    let mut wav_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--audio" => match args.next().and_then(|value| AudioBackend::parse(value)) {
+                Some(backend) => audio_backend = backend,
+                None => {
+                    eprintln!("Error: --audio needs one of device, silent or recording");
+                    std::process::exit(2);
+                }
+            },
+            "--wav" => match args.next() {
+                Some(value) => wav_path = Some(PathBuf::from(value)),
+                None => {
+                    eprintln!("Error: --wav needs a value");
+                    std::process::exit(2);
+                }
+            },
+            _ => rom_path = Some(PathBuf::from(arg)),
+        }
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1600.0, 900.0]),
         vsync: true,
@@ -48,8 +73,12 @@ fn main() {
         options,
         Box::new(|cc| {
             let mut app = Application::new(cc, None);
-            if let Some(path) = std::env::args().nth(1) {
-                app.open_file(PathBuf::from(path), &cc.egui_ctx);
+            app.tree_manager.state.audio_backend = audio_backend;
+            if let Some(path) = rom_path {
+                app.open_file(path, &cc.egui_ctx);
+                if let Some(wav_path) = wav_path {
+                    app.start_wav_recording(&wav_path);
+                }
             }
             Ok(Box::new(app))
         }),
@@ -65,10 +94,10 @@ struct Application {
 }
 
 impl Application {
-    pub fn new(cc: &eframe::CreationContext<'_>, cpu: Option<Box<CPU>>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, gameboy: Option<Box<GameBoy>>) -> Self {
         setup_fonts(&cc.egui_ctx);
         set_theme(&cc.egui_ctx);
-        let manager = TreeManager::new(cc, cpu);
+        let manager = TreeManager::new(cc, gameboy);
         let mut tiles = Tiles::default();
 
         let game_window = tiles.insert_pane(Pane::GameWindow(GameWindow::new()));
@@ -78,6 +107,10 @@ impl Application {
         let memory_dump = tiles.insert_pane(Pane::MemoryView(MemoryView::new()));
         let tile_map_viewer =
             tiles.insert_pane(Pane::TileMapViewer(TileMapViewer::new(&cc.egui_ctx)));
+        let audio = tiles.insert_pane(Pane::Audio(Audio::new()));
+        let apu_viewer = tiles.insert_pane(Pane::ApuViewer(ApuViewer::new()));
+        let tracker = tiles.insert_pane(Pane::Tracker(Tracker::new()));
+        let log = tiles.insert_pane(Pane::Log(Log::new()));
 
         let mut left_inner = Linear {
             children: vec![game_window, breakpoints, registers],
@@ -89,7 +122,7 @@ impl Application {
         left_inner.shares.set_share(registers, 0.3);
         let left = tiles.insert_new(Tile::Container(Container::Linear(left_inner)));
 
-        let right_tabs = tiles.insert_tab_tile(vec![memory_dump, tile_map_viewer]);
+        let right_tabs = tiles.insert_tab_tile(vec![memory_dump, tile_map_viewer, audio, apu_viewer, tracker, log]);
         let mut inner_right = Linear {
             children: vec![disassembly, right_tabs],
             dir: LinearDir::Horizontal,
@@ -115,34 +148,145 @@ impl Application {
     }
 
     pub fn open_file(&mut self, path: PathBuf, ctx: &egui::Context) {
+        // Symbols from the assembler, if there are any next to the ROM
+        self.tree_manager.state.symbols = SymbolTable::load(&path.with_extension("sym")).unwrap_or_default();
         let cartridge = Cartridge::new(path);
         let mut title = format!("GameBoy Debugger | {}", cartridge.get_title());
-        if let Some(licensee) = cartridge.get_licensee() {
+        if let Some(header) = cartridge.gbs_header() {
+            title = format!("GameBoy Debugger | {} | {}", header.title, header.author);
+        } else if let Some(licensee) = cartridge.get_licensee() {
             title += &format!(" | {}", licensee);
         }
         ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
         println!("MBC Type: ${:02X}", cartridge.get_mbc_type());
 
-        let player = CpalPlayer::get();
-        let (audio_player, stream) = match player {
-            Some((v, s)) => (Box::new(v) as Box<dyn AudioPlayer>, s),
-            None => return
-        };
-        let mut cpu = Box::new(CPU::new(cartridge, false, audio_player));
-        self.tree_manager.state.disassembler.disassemble(&mut cpu);
-        self.tree_manager.state.cpu = Some(cpu);
-        self.tree_manager.state.stream = Some(stream);
+        let audio_player = self.tree_manager.state.open_audio();
+        let mut gameboy = Box::new(GameBoy::from_cartridge(cartridge, false, audio_player));
+        self.tree_manager.state.disassembler.disassemble(&mut gameboy.cpu);
+        self.tree_manager.state.gameboy = Some(gameboy);
+        self.tree_manager.state.rewind.clear();
+        self.tree_manager.state.input_log.clear();
+        if let Err(e) = self.tree_manager.state.stop_movie() {
+            show_error(&format!("Could not save movie: {}", e));
+        }
     }
 
     pub fn open_dialog(&mut self, ctx: &egui::Context) {
         if let Ok(Some(path)) = native_dialog::FileDialog::new()
             .set_title("Open ROM")
             .add_filter("GameBoy ROM", &["gb", "gbc"])
+            .add_filter("GBS Music", &["gbs"])
             .show_open_single_file()
         {
             self.open_file(path, ctx);
         }
     }
+
+    pub fn load_symbols_dialog(&mut self) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Load Symbols")
+            .add_filter("Symbol File", &["sym"])
+            .show_open_single_file()
+        {
+            match SymbolTable::load(&path) {
+                Ok(symbols) => self.tree_manager.state.symbols = symbols,
+                Err(e) => show_error(&format!("Could not load symbols: {}", e)),
+            }
+        }
+    }
+
+    pub fn screenshot_dialog(&mut self) {
+        let Some(gameboy) = &self.tree_manager.state.gameboy else {
+            return;
+        };
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Save Screenshot")
+            .add_filter("PNG Image", &["png"])
+            .show_save_single_file()
+        {
+            let screenshot = Screenshot::from_screen_buffer(gameboy.framebuffer());
+            if let Err(e) = screenshot.save_png(&path.with_extension("png")) {
+                show_error(&format!("Could not save screenshot: {}", e));
+            }
+        }
+    }
+
+    pub fn wav_recording_dialog(&mut self) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Record WAV")
+            .add_filter("WAV Audio", &["wav"])
+            .show_save_single_file()
+        {
+            self.start_wav_recording(&path.with_extension("wav"));
+        }
+    }
+
+    pub fn start_wav_recording(&mut self, path: &Path) {
+        if let Some(gameboy) = &mut self.tree_manager.state.gameboy {
+            if let Err(e) = gameboy.cpu.mmu.sound.start_wav_recording(path) {
+                show_error(&format!("Could not record WAV: {}", e));
+            }
+        }
+    }
+
+    pub fn vgm_recording_dialog(&mut self) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Record VGM")
+            .add_filter("VGM Music", &["vgm"])
+            .show_save_single_file()
+        {
+            if let Some(gameboy) = &mut self.tree_manager.state.gameboy {
+                if let Err(e) = gameboy.cpu.mmu.sound.start_vgm_recording(&path.with_extension("vgm")) {
+                    show_error(&format!("Could not record VGM: {}", e));
+                }
+            }
+        }
+    }
+
+    pub fn save_recording_dialog(&mut self, recording: &Mutex<VecDeque<(f32, f32)>>) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Save Recording")
+            .add_filter("WAV Audio", &["wav"])
+            .show_save_single_file()
+        {
+            if let Err(e) = audio::save_recording(&path.with_extension("wav"), &recording.lock().unwrap()) {
+                show_error(&format!("Could not save the recording: {}", e));
+            }
+        }
+    }
+
+    pub fn record_movie_dialog(&mut self) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Record Movie")
+            .add_filter("GameBoy Movie", &["gbm"])
+            .show_save_single_file()
+        {
+            if let Err(e) = self.tree_manager.state.record_movie(path.with_extension("gbm")) {
+                show_error(&format!("Could not record movie: {}", e));
+            }
+        }
+    }
+
+    pub fn play_movie_dialog(&mut self) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Play Movie")
+            .add_filter("GameBoy Movie", &["gbm"])
+            .show_open_single_file()
+        {
+            if let Err(e) = self.tree_manager.state.play_movie(path) {
+                show_error(&format!("Could not play movie: {}", e));
+            }
+        }
+    }
+}
+
+pub fn show_error(message: &str) {
+    eprintln!("Error: {}", message);
+    let _ = native_dialog::MessageDialog::new()
+        .set_type(native_dialog::MessageType::Error)
+        .set_title("GameBoy Debugger")
+        .set_text(message)
+        .show_alert();
 }
 
 impl eframe::App for Application {
@@ -150,6 +294,11 @@ impl eframe::App for Application {
         if ctx.input(|i| i.key_pressed(egui::Key::O) && i.modifiers.ctrl) {
             self.open_dialog(ctx);
         }
+        if let Some(gameboy) = &mut self.tree_manager.state.gameboy {
+            if let Some(e) = gameboy.cpu.mmu.sound.take_recording_error() {
+                show_error(&format!("{:#}", e));
+            }
+        }
 
         TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             let style = ui.style_mut();
@@ -165,12 +314,102 @@ impl eframe::App for Application {
                         ui.close_menu();
                         self.open_dialog(ctx);
                     }
+                    let has_rom = self.tree_manager.state.gameboy.is_some();
+                    if ui.add_enabled(has_rom, Button::new("Save Screenshot")).clicked() {
+                        ui.close_menu();
+                        self.screenshot_dialog();
+                    }
+                    ui.separator();
+                    match &self.tree_manager.state.movie {
+                        None => {
+                            if ui.add_enabled(has_rom, Button::new("Record Movie")).clicked() {
+                                ui.close_menu();
+                                self.record_movie_dialog();
+                            }
+                            if ui.add_enabled(has_rom, Button::new("Play Movie")).clicked() {
+                                ui.close_menu();
+                                self.play_movie_dialog();
+                            }
+                        }
+                        Some(movie) => {
+                            let label = match movie.mode {
+                                MovieMode::Recording => format!("Stop Recording ({} frames)", movie.frame),
+                                MovieMode::Playback => {
+                                    format!("Stop Playback ({}/{})", movie.frame, movie.movie.frames.len())
+                                }
+                            };
+                            if ui.button(label).clicked() {
+                                ui.close_menu();
+                                if let Err(e) = self.tree_manager.state.stop_movie() {
+                                    show_error(&format!("Could not save movie: {}", e));
+                                }
+                            }
+                        }
+                    }
+                });
+                ui.menu_button("Audio", |ui| {
+                    ui.set_width(200.0);
+                    let state = &mut self.tree_manager.state;
+                    for backend in AudioBackend::ALL {
+                        if ui.radio(state.audio_backend == backend, backend.name()).clicked() {
+                            ui.close_menu();
+                            state.set_audio_backend(backend);
+                        }
+                    }
+                    ui.separator();
+                    let recording_wav =
+                        state.gameboy.as_ref().is_some_and(|gameboy| gameboy.cpu.mmu.sound.is_recording_wav());
+                    if recording_wav {
+                        if ui.button("Stop WAV Recording").clicked() {
+                            ui.close_menu();
+                            if let Some(gameboy) = &mut state.gameboy {
+                                if let Err(e) = gameboy.cpu.mmu.sound.stop_wav_recording() {
+                                    show_error(&format!("Could not save WAV: {}", e));
+                                }
+                            }
+                        }
+                    } else if ui.add_enabled(state.gameboy.is_some(), Button::new("Record WAV...")).clicked() {
+                        ui.close_menu();
+                        self.wav_recording_dialog();
+                    }
+                    let state = &mut self.tree_manager.state;
+                    let recording_vgm =
+                        state.gameboy.as_ref().is_some_and(|gameboy| gameboy.cpu.mmu.sound.is_recording_vgm());
+                    if recording_vgm {
+                        if ui.button("Stop VGM Recording").clicked() {
+                            ui.close_menu();
+                            if let Some(gameboy) = &mut state.gameboy {
+                                if let Err(e) = gameboy.cpu.mmu.sound.stop_vgm_recording() {
+                                    show_error(&format!("Could not save VGM: {}", e));
+                                }
+                            }
+                        }
+                    } else if ui.add_enabled(state.gameboy.is_some(), Button::new("Record VGM...")).clicked() {
+                        ui.close_menu();
+                        self.vgm_recording_dialog();
+                    }
+                    if let Some(recording) = self.tree_manager.state.audio_recording.clone() {
+                        let seconds = recording.lock().unwrap().len() as f32 / 44100.0;
+                        ui.separator();
+                        ui.label(format!("{:.1} s recorded (last 5 min kept)", seconds));
+                        if ui.button("Save Recording as WAV...").clicked() {
+                            ui.close_menu();
+                            self.save_recording_dialog(&recording);
+                        }
+                        if ui.button("Clear Recording").clicked() {
+                            recording.lock().unwrap().clear();
+                        }
+                    }
                 });
+                if let Some(warning) = &self.tree_manager.state.audio_warning {
+                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {}", warning));
+                }
                 ui.menu_button("Debug", |ui| {
                     ui.set_width(200.0);
                     if ui.button("Disassemble").clicked() {
                         ui.close_menu();
-                        if let Some(cpu) = &mut self.tree_manager.state.cpu {
+                        if let Some(gameboy) = &mut self.tree_manager.state.gameboy {
+                            let cpu = &mut gameboy.cpu;
                             self.tree_manager
                                 .state
                                 .disassembler
@@ -178,6 +417,33 @@ impl eframe::App for Application {
                             self.tree_manager.state.should_scroll_disasm = true;
                         }
                     }
+                    let symbol_count = self.tree_manager.state.symbols.len();
+                    if ui.button(format!("Load Symbols... ({} loaded)", symbol_count)).clicked() {
+                        ui.close_menu();
+                        self.load_symbols_dialog();
+                    }
+                    ui.menu_button("Rewind", |ui| {
+                        let rewind = &mut self.tree_manager.state.rewind;
+                        ui.checkbox(&mut rewind.enabled, "Enabled (hold Backspace)");
+                        ui.horizontal(|ui| {
+                            ui.label("Snapshot every");
+                            ui.add(DragValue::new(&mut rewind.frame_interval).range(1..=60));
+                            ui.label("frames");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Memory budget");
+                            let mut budget_mb = rewind.memory_budget / (1024 * 1024);
+                            if ui.add(DragValue::new(&mut budget_mb).range(1..=4096).suffix(" MiB")).changed() {
+                                rewind.memory_budget = budget_mb * 1024 * 1024;
+                                rewind.trim();
+                            }
+                        });
+                        ui.label(format!(
+                            "{} snapshots, {:.1} MiB used",
+                            rewind.snapshot_count(),
+                            rewind.memory_usage() as f32 / (1024.0 * 1024.0)
+                        ));
+                    });
                 });
             });
         });
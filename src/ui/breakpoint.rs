@@ -0,0 +1,282 @@
+use anyhow::{anyhow, Result};
+use gb_core::cpu::instruction::Instruction;
+use gb_core::cpu::CPU;
+use gb_core::debugger::DebugEvent;
+use gb_core::expression::{BreakCondition, LogFormat};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum BreakpointTrigger {
+    /// Stops when the PC reaches the address, only in `bank` if there is one. Banks are numbered like
+    /// `CPU::get_current_bank`.
+    Address { address: u16, bank: Option<u8> },
+    /// Stops when the CPU is about to execute the opcode, wherever it is.
+    Instruction { opcode: u8, prefixed: bool },
+    /// Checks the condition before every instruction.
+    Any,
+}
+
+impl BreakpointTrigger {
+    /// Parses a hexadecimal address like "4A10", or "05:4A10" to only stop in bank 5.
+    pub fn parse_address(text: &str) -> Result<BreakpointTrigger> {
+        let text = text.trim().trim_start_matches('$');
+        let (bank, address) = match text.split_once(':') {
+            Some((bank, address)) => (Some(u8::from_str_radix(bank, 16)?), address),
+            None => (None, text),
+        };
+        Ok(BreakpointTrigger::Address {
+            address: u16::from_str_radix(address, 16)?,
+            bank,
+        })
+    }
+
+    /// Whether this is a PC breakpoint on the instruction at `address` in `bank`.
+    pub fn is_at(&self, bank: u8, address: u16) -> bool {
+        match *self {
+            BreakpointTrigger::Address {
+                address: at,
+                bank: only_in,
+            } => at == address && only_in.is_none_or(|only_in| only_in == bank),
+            _ => false,
+        }
+    }
+
+    /// Parses a hexadecimal opcode like "D9", or "CB 7C" for a CB-prefixed one.
+    pub fn parse_instruction(text: &str) -> Result<BreakpointTrigger> {
+        let digits = text.replace(['$', ' ', ':'], "");
+        let (prefixed, opcode) = match digits.len() {
+            1 | 2 => (false, digits.as_str()),
+            4 if digits[..2].eq_ignore_ascii_case("CB") => (true, &digits[2..]),
+            _ => return Err(anyhow!("Expected an opcode like D9 or CB 7C")),
+        };
+        Ok(BreakpointTrigger::Instruction {
+            opcode: u8::from_str_radix(opcode, 16)?,
+            prefixed,
+        })
+    }
+
+    pub fn matches(&self, cpu: &mut CPU) -> bool {
+        let pc = cpu.registers.pc;
+        match *self {
+            BreakpointTrigger::Address { address, bank } => {
+                pc == address && bank.is_none_or(|bank| bank == cpu.get_current_bank())
+            }
+            BreakpointTrigger::Instruction { opcode, prefixed } => {
+                if prefixed {
+                    cpu.mmu.read_byte(pc) == 0xCB && cpu.mmu.read_byte(pc.wrapping_add(1)) == opcode
+                } else {
+                    cpu.mmu.read_byte(pc) == opcode
+                }
+            }
+            BreakpointTrigger::Any => true,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            BreakpointTrigger::Address { address, bank: None } => format!("{:04X}", address),
+            BreakpointTrigger::Address {
+                address,
+                bank: Some(bank),
+            } => format!("{:02X}:{:04X}", bank, address),
+            BreakpointTrigger::Instruction { opcode, prefixed } => {
+                let code = if prefixed {
+                    format!("CB {:02X}", opcode)
+                } else {
+                    format!("{:02X}", opcode)
+                };
+                match Instruction::from_byte(opcode, prefixed) {
+                    // Operands vary between hits, so only the mnemonic is shown for those
+                    Some(instruction) if prefixed || instruction.size() == 1 => {
+                        format!("Opcode {} ({})", code, instruction.to_string(0, 0, 0))
+                    }
+                    Some(instruction) => {
+                        let text = instruction.to_string(0, 0, 0);
+                        format!(
+                            "Opcode {} ({})",
+                            code,
+                            text.split_whitespace().next().unwrap_or_default()
+                        )
+                    }
+                    None => format!("Opcode {} (illegal)", code),
+                }
+            }
+            BreakpointTrigger::Any => "Any instruction".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Breakpoint {
+    pub trigger: BreakpointTrigger,
+    pub condition: BreakCondition,
+    /// Logs this message instead of stopping
+    pub log: Option<LogFormat>,
+}
+
+impl Breakpoint {
+    pub fn new(trigger: BreakpointTrigger) -> Breakpoint {
+        Breakpoint {
+            trigger,
+            condition: BreakCondition::default(),
+            log: None,
+        }
+    }
+
+    /// Whether the breakpoint applies to the next instruction, without counting a hit.
+    pub fn matches(&self, cpu: &mut CPU) -> bool {
+        self.trigger.matches(cpu) && self.condition.holds(cpu)
+    }
+
+    /// Counts a hit if the breakpoint applies to the next instruction and returns whether to stop.
+    pub fn check(&mut self, cpu: &mut CPU) -> bool {
+        self.trigger.matches(cpu) && self.condition.check(cpu)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = self.trigger.describe() + &self.condition.describe();
+        if let Some(log) = &self.log {
+            text += &format!(" log \"{}\"", log.text());
+        }
+        text
+    }
+}
+
+/// Where Step Over, Step Out and Run to Cursor stop, unless a breakpoint stops first.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RunTarget {
+    pub trigger: BreakpointTrigger,
+    /// Only stops with this stack pointer, so recursive calls do not stop early
+    pub stack_pointer: Option<u16>,
+}
+
+impl RunTarget {
+    pub fn reached(&self, cpu: &mut CPU) -> bool {
+        self.stack_pointer.is_none_or(|sp| sp == cpu.registers.sp) && self.trigger.matches(cpu)
+    }
+}
+
+/// Hardware events to stop on, checked after every instruction.
+#[derive(Default)]
+pub struct EventBreakpoints {
+    /// VBlank, LCD STAT, Timer, Serial and Joypad, by vector
+    pub interrupts: [bool; 5],
+    pub halt: bool,
+    pub stop: bool,
+    pub bank_switch: bool,
+    pub lcd_toggle: bool,
+    pub dma: bool,
+    /// Stops on writes to `io_register`
+    pub io_write: bool,
+    pub io_register: u16,
+    /// Stops on exceptions, which are always logged
+    pub exceptions: bool,
+}
+
+impl EventBreakpoints {
+    pub const INTERRUPT_NAMES: [&'static str; 5] = ["VBlank", "LCD STAT", "Timer", "Serial", "Joypad"];
+
+    pub fn new() -> EventBreakpoints {
+        EventBreakpoints {
+            io_register: 0xFF40,
+            ..Default::default()
+        }
+    }
+
+    pub fn any_enabled(&self) -> bool {
+        self.interrupts.contains(&true)
+            || self.halt
+            || self.stop
+            || self.bank_switch
+            || self.lcd_toggle
+            || self.dma
+            || self.io_write
+    }
+
+    pub fn matches(&self, event: &DebugEvent) -> bool {
+        match *event {
+            DebugEvent::Interrupt { vector } => {
+                let index = (vector.wrapping_sub(0x40) / 8) as usize;
+                self.interrupts.get(index).copied().unwrap_or(false)
+            }
+            DebugEvent::Halt => self.halt,
+            DebugEvent::Stop => self.stop,
+            DebugEvent::BankSwitch { .. } => self.bank_switch,
+            DebugEvent::LcdEnabled(_) => self.lcd_toggle,
+            DebugEvent::OamDma { .. } | DebugEvent::Hdma { .. } => self.dma,
+            DebugEvent::IoWrite { address, .. } => self.io_write && address == self.io_register,
+            // Nothing useful can run after the CPU locks up, so this always stops
+            DebugEvent::IllegalOpcode { .. } => true,
+            DebugEvent::Exception(_) => self.exceptions,
+        }
+    }
+}
+
+/// Points in the video output to run to, see `State::run_video_step`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum VideoStep {
+    /// Until LY changes
+    Scanline,
+    /// Until LY next becomes the line
+    Line(u8),
+    /// Until the PPU enters VBlank
+    VBlank,
+    /// Until the same line of the next frame
+    Frame,
+}
+
+// Oldest entries are dropped beyond this
+const MAX_LOG_ENTRIES: usize = 10000;
+
+pub struct LogEntry {
+    pub frame: u64,
+    pub cycle: u64,
+    pub text: String,
+}
+
+/// Messages from logpoints, optionally copied to a file as they come in.
+#[derive(Default)]
+pub struct DebugLog {
+    pub entries: VecDeque<LogEntry>,
+    file: Option<BufWriter<File>>,
+}
+
+impl DebugLog {
+    pub fn new() -> DebugLog {
+        DebugLog::default()
+    }
+
+    pub fn push(&mut self, entry: LogEntry) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = writeln!(file, "{:>8} {:>12} {}", entry.frame, entry.cycle, entry.text) {
+                self.file = None;
+                return Err(e.into());
+            }
+        }
+        if self.entries.len() >= MAX_LOG_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        Ok(())
+    }
+
+    pub fn start_file(&mut self, path: &Path) -> Result<()> {
+        self.stop_file()?;
+        self.file = Some(BufWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    pub fn stop_file(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_logging_to_file(&self) -> bool {
+        self.file.is_some()
+    }
+}
@@ -1,7 +1,9 @@
+mod breakpoint;
 mod elements;
 mod state;
 mod tree_behaviour;
 pub mod windows;
 pub use tree_behaviour::*;
 
+pub use breakpoint::*;
 pub use state::*;
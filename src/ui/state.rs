@@ -1,15 +1,41 @@
-use crate::cpu::CPU;
-use crate::disassembler::{Disassembler};
-use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ui::{Breakpoint, BreakpointTrigger, DebugLog, EventBreakpoints, LogEntry, RunTarget, VideoStep};
+use crate::show_error;
+use crate::audio::{AudioBackend, AudioCounters, AudioOutput, RateControl, SyncMode};
+use gb_core::disassembler::{Disassembler};
+use gb_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gb_core::movie::{MovieMode, MovieSession};
+use gb_core::rewind::{InputLog, RewindBuffer};
+use gb_core::io::sound::AudioPlayer;
+use gb_core::mmu::watchpoint::WatchHit;
+use gb_core::symbols::SymbolTable;
+use gb_core::tracker::CYCLES_PER_FRAME;
+use gb_core::cpu::CPU;
+use gb_core::debugger::{DebugEvent, ExceptionChecks};
+use gb_core::GameBoy;
+use anyhow::Result;
 use eframe::epaint::textures::TextureOptions;
 use eframe::epaint::TextureHandle;
 use eframe::egui;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 pub struct State {
-    pub cpu: Option<Box<CPU>>,
+    pub gameboy: Option<Box<GameBoy>>,
     pub texture: TextureHandle,
     pub cycles_elapsed_in_frame: usize,
-    pub breakpoints: Vec<u16>,
+    pub breakpoints: Vec<Breakpoint>,
+    // Labels for breakpoint conditions, loaded from the .sym file next to the ROM
+    pub symbols: SymbolTable,
+    // Watchpoint hits of the instruction that stopped execution last
+    pub watch_hits: Vec<WatchHit>,
+    pub debug_log: DebugLog,
+    pub event_breakpoints: EventBreakpoints,
+    pub exception_checks: ExceptionChecks,
+    // Events that stopped execution last
+    pub stop_events: Vec<DebugEvent>,
+    // Temporary stop of Step Over, Step Out or Run to Cursor
+    pub run_target: Option<RunTarget>,
     pub extra_targets: Vec<(u8, u16)>,
     pub disassembler: Disassembler,
     pub running: bool,
@@ -17,10 +43,21 @@ pub struct State {
     pub should_scroll_dump: bool,
     pub focussed_address: u16,
     pub stream: Option<cpal::Stream>,
+    pub audio_backend: AudioBackend,
+    pub audio_recording: Option<Arc<Mutex<VecDeque<(f32, f32)>>>>,
+    pub audio_warning: Option<String>,
+    // Underflow and overflow counts of the output device, None without one
+    pub audio_counters: Option<Arc<AudioCounters>>,
+    pub sync_mode: SyncMode,
+    pub rate_control: RateControl,
+    pub rewind: RewindBuffer,
+    pub input_log: InputLog,
+    pub movie: Option<MovieSession>,
+    host_buttons: u8,
 }
 
 impl State {
-    pub fn new(cc: &eframe::CreationContext<'_>, cpu: Option<Box<CPU>>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, gameboy: Option<Box<GameBoy>>) -> Self {
         let buffer = [0u8, 0u8, 0u8, 255u8]
             .iter()
             .cloned()
@@ -33,32 +70,105 @@ impl State {
             .egui_ctx
             .load_texture("color_buffer", color_image, TextureOptions::NEAREST);
         let mut disassembler = Disassembler::new();
-        let pc = if let Some(cpu) = &cpu {
-            //disassembler.disassemble(cpu);
-            cpu.registers.pc
+        let pc = if let Some(gameboy) = &gameboy {
+            //disassembler.disassemble(&gameboy.cpu);
+            gameboy.cpu.registers.pc
         } else {
             0
         };
         Self {
-            cpu,
+            gameboy,
             texture,
             cycles_elapsed_in_frame: 0,
             breakpoints: Vec::new(),
+            symbols: SymbolTable::new(),
+            watch_hits: Vec::new(),
+            debug_log: DebugLog::new(),
+            event_breakpoints: EventBreakpoints::new(),
+            exception_checks: ExceptionChecks::default(),
+            stop_events: Vec::new(),
+            run_target: None,
             disassembler,
             extra_targets: Vec::new(),
             running: false,
             should_scroll_disasm: true,
             should_scroll_dump: true,
             focussed_address: pc,
-            stream: None
+            stream: None,
+            audio_backend: AudioBackend::Device,
+            audio_recording: None,
+            audio_warning: None,
+            audio_counters: None,
+            sync_mode: SyncMode::Video,
+            rate_control: RateControl::new(),
+            rewind: RewindBuffer::new(),
+            input_log: InputLog::new(),
+            movie: None,
+            host_buttons: 0,
         }
     }
 
     pub fn step(&mut self) -> u8 {
-        if let Some(cpu) = &mut self.cpu {
-            // let prev = cpu.registers.pc;
-            // let byte = cpu.mmu.read_byte(cpu.registers.pc);
-            let cycles_elapsed = cpu.step();
+        if let Some(gameboy) = &mut self.gameboy {
+            // let prev = gameboy.cpu.registers.pc;
+            // let byte = gameboy.cpu.mmu.read_byte(gameboy.cpu.registers.pc);
+            self.input_log.truncate_after(gameboy.cpu.instruction_count);
+            let frame = gameboy.frame_count();
+            gameboy.cpu.mmu.watch_hits.clear();
+            gameboy.cpu.mmu.debug_events.enabled = self.event_breakpoints.any_enabled();
+            gameboy.cpu.mmu.debug_events.checks = self.exception_checks;
+            let location = (gameboy.cpu.get_current_bank(), gameboy.cpu.registers.pc);
+            let cycles_elapsed = gameboy.step_instruction().unwrap_or(0);
+            let cpu = &mut gameboy.cpu;
+            let mut events = cpu.mmu.debug_events.take();
+            for event in &events {
+                if let DebugEvent::Exception(exception) = event {
+                    let text = format!("{:02X}:{:04X} {}", location.0, location.1, exception.describe());
+                    Self::log(&mut self.debug_log, cpu, text);
+                }
+            }
+            events.retain(|event| self.event_breakpoints.matches(event));
+            if !events.is_empty() {
+                self.stop_events = events;
+                self.running = false;
+            }
+            if !cpu.mmu.watch_hits.is_empty() {
+                let mut hits = std::mem::take(&mut cpu.mmu.watch_hits);
+                // Conditions are evaluated after the instruction, with the watchpoints taken out so their
+                // memory reads cannot hit again
+                let mut watchpoints = std::mem::take(&mut cpu.mmu.watchpoints);
+                hits.retain(|hit| watchpoints[hit.watchpoint].condition.check(cpu));
+                // Logpoints report and let execution continue
+                let mut messages = Vec::new();
+                hits.retain(|hit| match &watchpoints[hit.watchpoint].log {
+                    Some(log) => {
+                        messages.push(log.format(cpu));
+                        false
+                    }
+                    None => true,
+                });
+                cpu.mmu.watchpoints = watchpoints;
+                for text in messages {
+                    Self::log(&mut self.debug_log, cpu, text);
+                }
+                if !hits.is_empty() {
+                    // Stop after the instruction that hit a watchpoint
+                    self.focussed_address = hits[0].address;
+                    self.watch_hits = hits;
+                    self.running = false;
+                    self.should_scroll_dump = true;
+                }
+            }
+            if let Some(movie) = &mut self.movie {
+                if cpu.mmu.ppu.frame_count != frame {
+                    movie.on_vblank(cpu, self.host_buttons);
+                    self.input_log.record(cpu.instruction_count, cpu.mmu.joypad.get_buttons());
+                    if movie.is_finished() {
+                        self.movie = None;
+                    }
+                }
+            }
+            self.rewind.on_step(cpu);
 
             let bank = cpu.get_current_bank();
             if !self.disassembler.explored_address(bank, cpu.registers.pc) {
@@ -101,4 +211,317 @@ impl State {
             0
         }
     }
-}
\ No newline at end of file
+
+    /// Opens the selected audio backend and returns its player, keeping the stream and any fallback warning.
+    pub fn open_audio(&mut self) -> Box<dyn AudioPlayer> {
+        let output = AudioOutput::open(self.audio_backend);
+        self.stream = output.stream;
+        self.audio_recording = output.recording;
+        self.audio_warning = output.warning;
+        self.audio_counters = output.counters;
+        output.player
+    }
+
+    /// Switches the audio backend, also for the running game.
+    pub fn set_audio_backend(&mut self, backend: AudioBackend) {
+        self.audio_backend = backend;
+        if self.gameboy.is_some() {
+            let player = self.open_audio();
+            if let Some(gameboy) = &mut self.gameboy {
+                gameboy.set_audio_player(player);
+            }
+        }
+    }
+
+    /// Steps back to the previous rewind snapshot, returns false if the rewind buffer is empty.
+    pub fn rewind(&mut self) -> bool {
+        let Some(gameboy) = &mut self.gameboy else {
+            return false;
+        };
+        if !self.rewind.rewind(&mut gameboy.cpu) {
+            return false;
+        }
+
+        self.after_time_travel();
+        true
+    }
+
+    /// Applies the buttons pressed on the host (see `Joypad::get_buttons`) and logs them,
+    /// so they are replayed when re-executing from a snapshot.
+    /// While a movie is active the buttons are only latched, the movie applies them at the next VBlank.
+    pub fn set_input(&mut self, buttons: u8) {
+        self.host_buttons = buttons;
+        if self.movie.is_some() {
+            return;
+        }
+        if let Some(gameboy) = &mut self.gameboy {
+            if gameboy.buttons() != buttons {
+                gameboy.set_buttons(buttons);
+                self.input_log.record(gameboy.cpu.instruction_count, buttons);
+            }
+        }
+    }
+
+    pub fn record_movie(&mut self, path: PathBuf) -> Result<()> {
+        if let Some(gameboy) = &mut self.gameboy {
+            self.movie = Some(MovieSession::record(&mut gameboy.cpu, path)?);
+            self.after_power_on();
+        }
+        Ok(())
+    }
+
+    pub fn play_movie(&mut self, path: PathBuf) -> Result<()> {
+        if let Some(gameboy) = &mut self.gameboy {
+            self.movie = Some(MovieSession::play(&mut gameboy.cpu, path)?);
+            self.after_power_on();
+        }
+        Ok(())
+    }
+
+    /// Ends the active movie, a recording is written to the file chosen when it was started.
+    pub fn stop_movie(&mut self) -> Result<()> {
+        match self.movie.take() {
+            Some(session) if session.mode == MovieMode::Recording => session.movie.save(&session.path),
+            _ => Ok(()),
+        }
+    }
+
+    fn after_power_on(&mut self) {
+        self.rewind.clear();
+        self.input_log.clear();
+        if let Some(gameboy) = &mut self.gameboy {
+            self.disassembler.disassembly.clear();
+            self.disassembler.disassemble(&mut gameboy.cpu);
+        }
+        self.after_time_travel();
+    }
+
+    /// Goes back exactly one instruction by restoring the nearest earlier snapshot and re-executing from it.
+    /// Returns false if no snapshot is old enough.
+    pub fn step_back(&mut self) -> bool {
+        let Some(gameboy) = &mut self.gameboy else {
+            return false;
+        };
+        let Some(target) = gameboy.cpu.instruction_count.checked_sub(1) else {
+            return false;
+        };
+        if !self.rewind.restore_before(&mut gameboy.cpu, target) {
+            return false;
+        }
+
+        self.replay_to(target);
+        self.after_time_travel();
+        true
+    }
+
+    /// Runs backwards until the most recent earlier point where the PC was on a breakpoint.
+    /// Stops at the oldest snapshot and returns false if no breakpoint was hit within the rewind buffer.
+    pub fn reverse_continue(&mut self) -> bool {
+        let Some(gameboy) = &self.gameboy else {
+            return false;
+        };
+
+        // Search one snapshot interval at a time, starting with the most recent one
+        let mut search_end = gameboy.cpu.instruction_count;
+        let mut found = false;
+        while let Some(gameboy) = &mut self.gameboy {
+            let cpu = &mut gameboy.cpu;
+            if search_end == 0 || !self.rewind.restore_before(cpu, search_end - 1) {
+                break;
+            }
+            let interval_start = cpu.instruction_count;
+            if let Some(hit) = self.replay_to(search_end) {
+                if let Some(gameboy) = &mut self.gameboy {
+                    self.rewind.restore_before(&mut gameboy.cpu, hit);
+                }
+                self.replay_to(hit);
+                found = true;
+                break;
+            }
+            search_end = interval_start;
+        }
+
+        self.after_time_travel();
+        found
+    }
+
+    // Re-executes up to `instruction_count`, feeding back the logged input.
+    // Returns the last point on the way where the PC was on a breakpoint, the end point itself excluded.
+    fn replay_to(&mut self, instruction_count: u64) -> Option<u64> {
+        let gameboy = self.gameboy.as_mut()?;
+        let mut last_hit = None;
+        while gameboy.cpu.instruction_count < instruction_count {
+            let cpu = &mut gameboy.cpu;
+            if self.breakpoints.iter().any(|breakpoint| breakpoint.log.is_none() && breakpoint.matches(cpu)) {
+                last_hit = Some(cpu.instruction_count);
+            }
+            if let Some(buttons) = self.input_log.change_at(cpu.instruction_count) {
+                cpu.mmu.joypad.set_buttons(buttons);
+            }
+            // Through the facade like `step`, so a GBS driver runs the same way as the first time
+            gameboy.step_instruction();
+            self.rewind.on_step(&gameboy.cpu);
+        }
+        // The events of replayed instructions were reported the first time round
+        gameboy.cpu.mmu.debug_events.take();
+        last_hit
+    }
+
+    /// Checks the breakpoints before the next instruction, logging the logpoints that hit.
+    /// Returns whether a breakpoint asks to stop.
+    pub fn check_breakpoints(&mut self) -> bool {
+        let Some(gameboy) = &mut self.gameboy else {
+            return false;
+        };
+        let cpu = &mut gameboy.cpu;
+        let mut stop = false;
+        // Every breakpoint is checked so all of their hit counts stay up to date
+        for breakpoint in &mut self.breakpoints {
+            if !breakpoint.check(cpu) {
+                continue;
+            }
+            match &breakpoint.log {
+                Some(log) => {
+                    let text = log.format(cpu);
+                    Self::log(&mut self.debug_log, cpu, text);
+                }
+                None => stop = true,
+            }
+        }
+        stop
+    }
+
+    pub fn reached_run_target(&mut self) -> bool {
+        match (&self.run_target, &mut self.gameboy) {
+            (Some(target), Some(gameboy)) => target.reached(&mut gameboy.cpu),
+            _ => false,
+        }
+    }
+
+    /// Steps once and keeps running until `target`.
+    fn run_until(&mut self, target: RunTarget) {
+        self.run_target = Some(target);
+        // A watchpoint hit in this first step stops it again
+        self.running = true;
+        self.cycles_elapsed_in_frame += self.step() as usize;
+    }
+
+    /// Runs a CALL or RST until it returns, any other instruction is just stepped.
+    pub fn step_over(&mut self) {
+        let Some(gameboy) = &mut self.gameboy else {
+            return;
+        };
+        let cpu = &mut gameboy.cpu;
+        let pc = cpu.registers.pc;
+        let size = match cpu.mmu.read_byte(pc) {
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+            opcode if opcode & 0xC7 == 0xC7 => 1,
+            _ => {
+                self.cycles_elapsed_in_frame += self.step() as usize;
+                return;
+            }
+        };
+        let target = RunTarget {
+            trigger: BreakpointTrigger::Address {
+                address: pc.wrapping_add(size),
+                bank: None,
+            },
+            stack_pointer: Some(cpu.registers.sp),
+        };
+        self.run_until(target);
+    }
+
+    /// Runs until the current function returns to its caller.
+    pub fn step_out(&mut self) {
+        let Some(call) = self.gameboy.as_ref().and_then(|gameboy| gameboy.cpu.call_stack.last()) else {
+            return;
+        };
+        let target = RunTarget {
+            trigger: BreakpointTrigger::Address {
+                address: call.return_address,
+                bank: None,
+            },
+            // The return address has been popped by then
+            stack_pointer: Some(call.stack_address.wrapping_add(2)),
+        };
+        self.run_until(target);
+    }
+
+    pub fn run_to(&mut self, bank: u8, address: u16) {
+        let target = RunTarget {
+            trigger: BreakpointTrigger::Address {
+                address,
+                bank: Some(bank),
+            },
+            stack_pointer: None,
+        };
+        self.run_until(target);
+    }
+
+    /// Runs right away until the PPU reaches `step`, or a breakpoint stops it, and shows the frame as it is.
+    /// Gives up after two frames, which happens while the LCD is off.
+    pub fn run_video_step(&mut self, step: VideoStep) {
+        let Some(gameboy) = &self.gameboy else {
+            return;
+        };
+        let ppu = &gameboy.cpu.mmu.ppu;
+        let (start_frame, start_ly) = (ppu.frame_count, ppu.ly());
+        let mut previous_ly = start_ly;
+        let mut cycles = 0;
+        // A watchpoint hit clears this
+        self.running = true;
+        while cycles < 2 * CYCLES_PER_FRAME as usize {
+            cycles += self.step() as usize;
+            let Some(gameboy) = &self.gameboy else {
+                break;
+            };
+            let ppu = &gameboy.cpu.mmu.ppu;
+            let (frame, ly) = (ppu.frame_count, ppu.ly());
+            let reached = match step {
+                VideoStep::Scanline => ly != start_ly,
+                VideoStep::Line(line) => ly == line && previous_ly != line,
+                VideoStep::VBlank => frame != start_frame,
+                VideoStep::Frame => frame != start_frame && ly == start_ly,
+            };
+            previous_ly = ly;
+            if reached || !self.running || self.check_breakpoints() {
+                break;
+            }
+        }
+        self.running = false;
+        self.run_target = None;
+        self.update_texture();
+        self.cycles_elapsed_in_frame = 0;
+    }
+
+    fn log(debug_log: &mut DebugLog, cpu: &CPU, text: String) {
+        let entry = LogEntry {
+            frame: cpu.mmu.ppu.frame_count,
+            cycle: cpu.cycle_count,
+            text,
+        };
+        if let Err(e) = debug_log.push(entry) {
+            show_error(&format!("Could not write the log file: {}", e));
+        }
+    }
+
+    fn after_time_travel(&mut self) {
+        if let Some(gameboy) = &self.gameboy {
+            self.focussed_address = gameboy.cpu.registers.pc;
+        }
+        self.cycles_elapsed_in_frame = 0;
+        self.should_scroll_disasm = true;
+        self.should_scroll_dump = true;
+        self.update_texture();
+    }
+
+    /// Copies the PPU screen buffer into the texture shown by the game window.
+    pub fn update_texture(&mut self) {
+        if let Some(gameboy) = &mut self.gameboy {
+            let color_image =
+                egui::ColorImage::from_rgb([SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize], gameboy.framebuffer());
+            self.texture.set(color_image, TextureOptions::NEAREST);
+            gameboy.cpu.mmu.ppu.screen_buffer_updated = false;
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use crate::cpu::CPU;
 use crate::ui::windows::*;
 use crate::ui::State;
+use gb_core::GameBoy;
 use eframe::egui;
 use eframe::emath::{vec2, Vec2};
 use eframe::epaint::Stroke;
@@ -15,6 +15,10 @@ pub enum Pane {
     Registers(Registers),
     MemoryView(MemoryView),
     TileMapViewer(TileMapViewer),
+    Audio(Audio),
+    ApuViewer(ApuViewer),
+    Tracker(Tracker),
+    Log(Log),
 }
 
 impl Pane {
@@ -26,6 +30,10 @@ impl Pane {
             Pane::Registers(view) => view.show(state, ui),
             Pane::MemoryView(view) => view.show(state, ui),
             Pane::TileMapViewer(view) => view.show(state, ui),
+            Pane::Audio(view) => view.show(state, ui),
+            Pane::ApuViewer(view) => view.show(state, ui),
+            Pane::Tracker(view) => view.show(state, ui),
+            Pane::Log(view) => view.show(state, ui),
         }
         egui_tiles::UiResponse::None
     }
@@ -37,13 +45,13 @@ pub struct TreeManager {
 }
 
 impl TreeManager {
-    pub fn new(cc: &eframe::CreationContext<'_>, cpu: Option<Box<CPU>>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, gameboy: Option<Box<GameBoy>>) -> Self {
         let mut simplification_options = egui_tiles::SimplificationOptions::default();
         simplification_options.all_panes_must_have_tabs = true;
 
         Self {
             simplification_options,
-            state: State::new(cc, cpu),
+            state: State::new(cc, gameboy),
         }
     }
 }
@@ -66,6 +74,10 @@ impl egui_tiles::Behavior<Pane> for TreeManager {
             Pane::Registers(_) => "Registers".into(),
             Pane::MemoryView(_) => "Memory View".into(),
             Pane::TileMapViewer(_) => "Tile Map Viewer".into(),
+            Pane::Audio(_) => "Audio".into(),
+            Pane::ApuViewer(_) => "APU Viewer".into(),
+            Pane::Tracker(_) => "Tracker".into(),
+            Pane::Log(_) => "Log".into(),
         }
     }
 
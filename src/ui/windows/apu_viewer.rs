@@ -0,0 +1,259 @@
+use crate::ui::windows::{Window, CHANNEL_NAMES};
+use crate::ui::State;
+use eframe::egui;
+use eframe::egui::{Color32, Grid, Pos2, Rect, ScrollArea, Sense, Shape, Stroke, Ui, Vec2};
+use gb_core::io::sound::{midi_note, note_name, ChannelStatus, Sound};
+
+// Samples shown by the oscilloscope, the rest of the scope buffer is used to find a trigger point
+const SCOPE_WINDOW: usize = 512;
+const SCOPE_HEIGHT: f32 = 60.0;
+const WAVE_EDITOR_HEIGHT: f32 = 96.0;
+
+const CHANNEL_REGISTERS: [&[u16]; 4] = [
+    &[0xFF10, 0xFF11, 0xFF12, 0xFF13, 0xFF14],
+    &[0xFF16, 0xFF17, 0xFF18, 0xFF19],
+    &[0xFF1A, 0xFF1B, 0xFF1C, 0xFF1D, 0xFF1E],
+    &[0xFF20, 0xFF21, 0xFF22, 0xFF23],
+];
+
+pub struct ApuViewer {}
+
+impl ApuViewer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn register_name(address: u16) -> &'static str {
+        match address {
+            0xFF10 => "NR10",
+            0xFF11 => "NR11",
+            0xFF12 => "NR12",
+            0xFF13 => "NR13",
+            0xFF14 => "NR14",
+            0xFF16 => "NR21",
+            0xFF17 => "NR22",
+            0xFF18 => "NR23",
+            0xFF19 => "NR24",
+            0xFF1A => "NR30",
+            0xFF1B => "NR31",
+            0xFF1C => "NR32",
+            0xFF1D => "NR33",
+            0xFF1E => "NR34",
+            0xFF20 => "NR41",
+            0xFF21 => "NR42",
+            0xFF22 => "NR43",
+            0xFF23 => "NR44",
+            0xFF24 => "NR50",
+            0xFF25 => "NR51",
+            0xFF26 => "NR52",
+            _ => "",
+        }
+    }
+
+    fn show_decode(ui: &mut Ui, channel: usize, status: &ChannelStatus, output: u8) {
+        Grid::new(("apu_decode", channel)).num_columns(2).show(ui, |ui| {
+            ui.label("Status");
+            ui.label(format!(
+                "{}{}",
+                if status.active { "Playing" } else { "Stopped" },
+                if status.dac_enabled { "" } else { ", DAC off" }
+            ));
+            ui.end_row();
+
+            if let Some(duty) = status.duty {
+                ui.label("Duty");
+                ui.label(["12.5%", "25%", "50%", "75%"][duty as usize]);
+                ui.end_row();
+            }
+            if let Some(sweep) = &status.sweep {
+                ui.label("Sweep");
+                ui.label(format!(
+                    "Period {}, {} shift {}",
+                    sweep.period,
+                    if sweep.negate { "down" } else { "up" },
+                    sweep.shift
+                ));
+                ui.end_row();
+            }
+            if let Some(envelope) = &status.envelope {
+                ui.label("Envelope");
+                ui.label(format!(
+                    "Start {}, {} every {}",
+                    envelope.initial_volume,
+                    if envelope.goes_up { "up" } else { "down" },
+                    envelope.period
+                ));
+                ui.end_row();
+            }
+
+            ui.label("Output");
+            ui.label(format!("{:X}", output));
+            ui.end_row();
+
+            ui.label("Volume");
+            if channel == 2 {
+                ui.label(["Muted", "100%", "50%", "25%"][status.volume as usize]);
+            } else {
+                ui.label(format!("{}", status.volume));
+            }
+            ui.end_row();
+
+            ui.label("Length");
+            ui.label(format!(
+                "{}{}",
+                status.length,
+                if status.length_enabled { "" } else { " (disabled)" }
+            ));
+            ui.end_row();
+
+            if let Some(noise) = &status.noise {
+                ui.label("Noise");
+                ui.label(format!(
+                    "Shift {}, divisor {}, {} bit",
+                    noise.clock_shift,
+                    noise.divisor_code,
+                    if noise.short_mode { 7 } else { 15 }
+                ));
+                ui.end_row();
+            }
+
+            ui.label("Frequency");
+            match status.frequency {
+                Some(frequency) if status.frequency_hz > 0.0 => {
+                    let note = midi_note(status.frequency_hz);
+                    let nearest = note.round() as i32;
+                    ui.label(format!(
+                        "${:03X}  {:.1} Hz  {} {:+}c",
+                        frequency,
+                        status.frequency_hz,
+                        note_name(nearest),
+                        ((note - nearest as f32) * 100.0).round() as i32
+                    ));
+                }
+                _ => {
+                    ui.label(format!("{:.1} Hz", status.frequency_hz));
+                }
+            }
+            ui.end_row();
+        });
+    }
+
+    fn show_scope(ui: &mut Ui, samples: &[f32]) {
+        let width = ui.available_width().max(100.0);
+        let (response, painter) = ui.allocate_painter(Vec2::new(width, SCOPE_HEIGHT), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        painter.hline(rect.x_range(), rect.center().y, Stroke::new(1.0, ui.visuals().faint_bg_color));
+
+        // Start at the first rising edge so periodic waveforms stand still
+        let search = samples.len().saturating_sub(SCOPE_WINDOW);
+        let start = (1..search)
+            .find(|&i| samples[i - 1] <= 0.0 && samples[i] > 0.0)
+            .unwrap_or(search);
+        let window = &samples[start..(start + SCOPE_WINDOW).min(samples.len())];
+
+        let points = window
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                Pos2::new(
+                    rect.left() + i as f32 * rect.width() / SCOPE_WINDOW as f32,
+                    rect.center().y - sample.clamp(-1.0, 1.0) * (rect.height() / 2.0 - 2.0),
+                )
+            })
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+    }
+
+    // Draws the 32 wave samples as bars, clicking or dragging sets the sample under the pointer
+    fn show_wave_editor(ui: &mut Ui, sound: &mut Sound) {
+        let width = ui.available_width().max(100.0);
+        let (response, painter) =
+            ui.allocate_painter(Vec2::new(width, WAVE_EDITOR_HEIGHT), Sense::click_and_drag());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+        let bar_width = rect.width() / 32.0;
+        let level_height = rect.height() / 16.0;
+        if let Some(pointer) = response.interact_pointer_pos() {
+            if rect.contains(pointer) {
+                // The edges are inside the rect, so both can be one past the last bar or level
+                let index = ((pointer.x - rect.left()) / bar_width) as usize;
+                let level = ((pointer.y - rect.top()) / level_height) as u8;
+                sound.set_wave_sample(index.min(31), 15 - level.min(15));
+            }
+        }
+
+        let wave_ram = sound.wave_ram();
+        for index in 0..32 {
+            let byte = wave_ram[index / 2];
+            let value = if index % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+            let left = rect.left() + index as f32 * bar_width;
+            let bar = Rect::from_min_max(
+                Pos2::new(left + 1.0, rect.bottom() - (value as f32 + 1.0) * level_height),
+                Pos2::new(left + bar_width - 1.0, rect.bottom()),
+            );
+            painter.rect_filled(bar, 0.0, Color32::from_rgb(90, 140, 220));
+        }
+
+        ui.label(
+            wave_ram
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+}
+
+impl Window for ApuViewer {
+    fn show(&mut self, state: &mut State, ui: &mut egui::Ui) {
+        let Some(gameboy) = &mut state.gameboy else {
+            return;
+        };
+        let sound = &mut gameboy.cpu.mmu.sound;
+
+        ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            let nr50 = sound.rb(0xFF24);
+            let nr51 = sound.rb(0xFF25);
+            let nr52 = sound.rb(0xFF26);
+            let pcm12 = sound.pcm12();
+            let pcm34 = sound.pcm34();
+            ui.label(format!(
+                "NR50: {:02X}  NR51: {:02X}  NR52: {:02X}  PCM12: {:02X}  PCM34: {:02X}",
+                nr50, nr51, nr52, pcm12, pcm34
+            ));
+            ui.label(format!(
+                "APU {}, master volume L {} R {}",
+                if nr52 & 0x80 != 0 { "on" } else { "off" },
+                (nr50 >> 4) & 7,
+                nr50 & 7
+            ));
+
+            for (channel, name) in CHANNEL_NAMES.iter().enumerate() {
+                ui.separator();
+                let status = sound.channel_status(channel);
+                let pan = match (nr51 & (0x10 << channel) != 0, nr51 & (0x01 << channel) != 0) {
+                    (true, true) => "L R",
+                    (true, false) => "L",
+                    (false, true) => "R",
+                    (false, false) => "-",
+                };
+                ui.strong(format!("{} {}. {}  [{}]", if status.active { "●" } else { "○" }, channel + 1, name, pan));
+
+                let registers = CHANNEL_REGISTERS[channel]
+                    .iter()
+                    .map(|&address| format!("{}: {:02X}", Self::register_name(address), sound.rb(address)))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                ui.label(registers);
+
+                Self::show_decode(ui, channel, &status, sound.digital_output(channel));
+                Self::show_scope(ui, &sound.scope(channel));
+                if channel == 2 {
+                    Self::show_wave_editor(ui, sound);
+                }
+            }
+        });
+    }
+}
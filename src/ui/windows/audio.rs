@@ -0,0 +1,153 @@
+use crate::audio::SyncMode;
+use crate::ui::windows::Window;
+use crate::show_error;
+use crate::ui::State;
+use eframe::egui;
+use std::sync::atomic::Ordering;
+
+pub const CHANNEL_NAMES: [&str; 4] = ["Square 1", "Square 2", "Wave", "Noise"];
+
+pub struct Audio {}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn stems_dialog(state: &mut State) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Record Stems")
+            .add_filter("WAV Audio", &["wav"])
+            .show_save_single_file()
+        {
+            if let Some(gameboy) = &mut state.gameboy {
+                if let Err(e) = gameboy.cpu.mmu.sound.start_stem_recording(&path.with_extension("wav")) {
+                    show_error(&format!("Could not record stems: {}", e));
+                }
+            }
+        }
+    }
+
+    fn show_sync(state: &mut State, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for mode in SyncMode::ALL {
+                if ui.radio_value(&mut state.sync_mode, mode, mode.name()).changed() && mode != SyncMode::Audio {
+                    if let Some(gameboy) = &mut state.gameboy {
+                        gameboy.cpu.mmu.sound.set_rate_adjustment(1.0);
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Target latency");
+            ui.add(
+                egui::DragValue::new(&mut state.rate_control.target_latency_ms)
+                    .range(20.0..=500.0)
+                    .suffix(" ms"),
+            );
+        });
+
+        let Some(gameboy) = &state.gameboy else {
+            return;
+        };
+        let player = gameboy.audio_player();
+        let (Some(buffered), Some(counters)) = (player.buffered_samples(), &state.audio_counters) else {
+            ui.label("No output device, emulation is paced by time");
+            return;
+        };
+        egui::Grid::new("audio_sync").show(ui, |ui| {
+            ui.label("Latency");
+            ui.label(format!("{:.0} ms", buffered as f32 * 1000.0 / player.samples_rate() as f32));
+            ui.end_row();
+            ui.label("Rate adjustment");
+            ui.label(format!("{:+.2}%", (gameboy.cpu.mmu.sound.rate_adjustment() - 1.0) * 100.0));
+            ui.end_row();
+            ui.label("Underflows");
+            ui.label(format!("{}", counters.underflows.load(Ordering::Relaxed)));
+            ui.end_row();
+            ui.label("Dropped samples");
+            ui.label(format!("{}", counters.dropped_samples.load(Ordering::Relaxed)));
+            ui.end_row();
+        });
+        if ui.button("Reset Counters").clicked() {
+            counters.underflows.store(0, Ordering::Relaxed);
+            counters.dropped_samples.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Window for Audio {
+    fn show(&mut self, state: &mut State, ui: &mut egui::Ui) {
+        let Some(gameboy) = &mut state.gameboy else {
+            return;
+        };
+        if let Some((header, song)) = gameboy.gbs().map(|gbs| (gbs.header.clone(), gbs.song())) {
+            ui.strong(&header.title);
+            ui.label(format!("{} - {}", header.author, header.copyright));
+            let rate = if header.uses_timer() {
+                format!("timer, {:.1} Hz", 4194304.0 / header.play_period() as f64)
+            } else {
+                "VBlank".to_string()
+            };
+            ui.horizontal(|ui| {
+                if ui.button("⏮").on_hover_text("Previous song").clicked() {
+                    gameboy.previous_song();
+                }
+                if ui.button("⟲").on_hover_text("Restart song").clicked() {
+                    gameboy.play_song(song);
+                }
+                if ui.button("⏭").on_hover_text("Next song").clicked() {
+                    gameboy.next_song();
+                }
+                ui.label(format!("Song {} / {}  ({})", song + 1, header.song_count, rate));
+            });
+            ui.separator();
+        }
+
+        let sound = &mut gameboy.cpu.mmu.sound;
+        let mut record_stems = false;
+
+        egui::Grid::new("audio_channels").striped(true).show(ui, |ui| {
+            ui.label("Channel");
+            ui.label("Mute");
+            ui.label("Solo");
+            ui.end_row();
+
+            for (channel, name) in CHANNEL_NAMES.iter().enumerate() {
+                let label = egui::RichText::new(*name);
+                if sound.channel_audible(channel) {
+                    ui.label(label);
+                } else {
+                    ui.label(label.weak());
+                }
+                ui.checkbox(&mut sound.muted[channel], "");
+                ui.checkbox(&mut sound.soloed[channel], "");
+                ui.end_row();
+            }
+        });
+
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if ui.button("Unmute All").clicked() {
+                sound.muted = [false; 4];
+                sound.soloed = [false; 4];
+            }
+            if sound.is_recording_stems() {
+                if ui.button("Stop Stems").clicked() {
+                    if let Err(e) = sound.stop_stem_recording() {
+                        show_error(&format!("Could not save stems: {}", e));
+                    }
+                }
+            } else if ui.button("Record Stems...").clicked() {
+                record_stems = true;
+            }
+        });
+
+        if record_stems {
+            Self::stems_dialog(state);
+        }
+
+        ui.separator();
+        Self::show_sync(state, ui);
+    }
+}
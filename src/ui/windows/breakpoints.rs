@@ -1,27 +1,38 @@
 use crate::ui::windows::Window;
-use crate::ui::State;
+use crate::ui::{Breakpoint, BreakpointTrigger, EventBreakpoints, State};
+use anyhow::{anyhow, Result};
 use eframe::egui;
-use eframe::egui::{Button, ComboBox, Id, Modal, Sides, Widget};
+use eframe::egui::{Button, CollapsingHeader, ComboBox, DragValue, Id, Modal, Sides, Widget};
+use gb_core::expression::{BreakCondition, LogFormat};
+use gb_core::mmu::watchpoint::{WatchKind, Watchpoint};
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum BreakpointType {
     Address,
     Instruction,
-}
-
-pub enum Breakpoint {
-    Address(u16),
-    Instruction(u8),
+    Watchpoint,
+    Condition,
 }
 
 impl BreakpointType {
-    pub const VALUES: [BreakpointType; 2] = [BreakpointType::Address, BreakpointType::Instruction];
+    pub const VALUES: [BreakpointType; 4] = [
+        BreakpointType::Address,
+        BreakpointType::Instruction,
+        BreakpointType::Watchpoint,
+        BreakpointType::Condition,
+    ];
 }
 
 pub struct Breakpoints {
     pub show_message_box: bool,
     pub breakpoint_input_buffer: String,
     breakpoint_type: BreakpointType,
+    watch_kind: WatchKind,
+    watch_value_buffer: String,
+    condition_buffer: String,
+    hit_buffer: String,
+    log_buffer: String,
+    error: Option<String>,
 }
 
 impl Breakpoints {
@@ -30,31 +41,155 @@ impl Breakpoints {
             show_message_box: false,
             breakpoint_input_buffer: String::new(),
             breakpoint_type: BreakpointType::Address,
+            watch_kind: WatchKind::Write,
+            watch_value_buffer: String::new(),
+            condition_buffer: String::new(),
+            hit_buffer: String::new(),
+            log_buffer: String::new(),
+            error: None,
+        }
+    }
+
+    fn add(&mut self, state: &mut State) -> Result<()> {
+        let condition = BreakCondition::parse(&self.condition_buffer, &self.hit_buffer, &state.symbols)?;
+        let log = match self.log_buffer.trim() {
+            "" => None,
+            _ => Some(LogFormat::parse(&self.log_buffer, &state.symbols)?),
+        };
+        let trigger = match self.breakpoint_type {
+            BreakpointType::Address => BreakpointTrigger::parse_address(&self.breakpoint_input_buffer)?,
+            BreakpointType::Instruction => BreakpointTrigger::parse_instruction(&self.breakpoint_input_buffer)?,
+            BreakpointType::Condition if condition.expression.is_none() => {
+                return Err(anyhow!("A condition breakpoint needs a condition"));
+            }
+            BreakpointType::Condition => BreakpointTrigger::Any,
+            BreakpointType::Watchpoint => {
+                let mut watchpoint =
+                    Watchpoint::parse(&self.breakpoint_input_buffer, self.watch_kind, &self.watch_value_buffer)?;
+                watchpoint.condition = condition;
+                watchpoint.log = log;
+                let Some(gameboy) = &mut state.gameboy else {
+                    return Err(anyhow!("Watchpoints need a loaded ROM"));
+                };
+                gameboy.cpu.mmu.watchpoints.push(watchpoint);
+                return Ok(());
+            }
+        };
+        state.breakpoints.push(Breakpoint { trigger, condition, log });
+        Ok(())
+    }
+
+    fn show_events(state: &mut State, ui: &mut egui::Ui) {
+        CollapsingHeader::new("Event Breakpoints").show(ui, |ui| {
+            let events = &mut state.event_breakpoints;
+            ui.horizontal_wrapped(|ui| {
+                for (enabled, name) in events.interrupts.iter_mut().zip(EventBreakpoints::INTERRUPT_NAMES) {
+                    ui.checkbox(enabled, format!("{} interrupt", name));
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.checkbox(&mut events.halt, "HALT");
+                ui.checkbox(&mut events.stop, "STOP");
+                ui.checkbox(&mut events.bank_switch, "ROM bank switch");
+                ui.checkbox(&mut events.lcd_toggle, "LCD on/off");
+                ui.checkbox(&mut events.dma, "DMA start");
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut events.io_write, "Write to");
+                ui.add(
+                    DragValue::new(&mut events.io_register)
+                        .range(0xFF00..=0xFFFF)
+                        .hexadecimal(4, false, true)
+                        .prefix("$"),
+                );
+            });
+        });
+
+        CollapsingHeader::new("Exception Checks").show(ui, |ui| {
+            let checks = &mut state.exception_checks;
+            ui.checkbox(&mut checks.rom_write, "ROM write without MBC effect");
+            ui.checkbox(&mut checks.uninitialized_read, "Uninitialized WRAM read");
+            ui.checkbox(&mut checks.stack_pointer, "Stack pointer outside WRAM/HRAM");
+            ui.checkbox(&mut checks.execution, "Execution outside ROM/HRAM");
+            ui.checkbox(&mut checks.vram_write, "VRAM write during mode 3");
+            ui.checkbox(&mut checks.hang, "HALT with IE = 0");
+            ui.checkbox(&mut checks.software_breakpoint, "ld b,b software breakpoint");
+            ui.separator();
+            ui.checkbox(&mut state.event_breakpoints.exceptions, "Stop on exceptions")
+                .on_hover_text("Exceptions are always written to the Log pane");
+        });
+
+        if !state.stop_events.is_empty() {
+            ui.label("Stopped by:");
+            for event in &state.stop_events {
+                ui.label(event.describe());
+            }
+        }
+    }
+
+    fn show_watchpoints(state: &mut State, ui: &mut egui::Ui) {
+        let Some(gameboy) = &mut state.gameboy else {
+            return;
+        };
+        let cpu = &mut gameboy.cpu;
+        let mut deletion = None;
+        for (index, watchpoint) in cpu.mmu.watchpoints.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("Remove").clicked() {
+                    deletion = Some(index);
+                }
+                ui.label(watchpoint.describe());
+            });
+        }
+        if let Some(index) = deletion {
+            cpu.mmu.watchpoints.remove(index);
+        }
+
+        if !state.watch_hits.is_empty() {
+            ui.separator();
+            ui.label("Stopped by:");
+            for hit in &state.watch_hits {
+                let access = if hit.write {
+                    format!("Write ${:04X}: ${:02X} -> ${:02X}", hit.address, hit.old_value, hit.new_value)
+                } else {
+                    format!("Read ${:04X}: ${:02X}", hit.address, hit.new_value)
+                };
+                ui.label(format!("{} at ${:04X} {}", access, hit.pc, cpu.describe_instruction(hit.pc)));
+            }
         }
     }
 }
 
 impl Window for Breakpoints {
     fn show(&mut self, state: &mut State, ui: &mut egui::Ui) {
-        let mut deletion = Vec::new();
-        for bp in state.breakpoints.iter() {
+        let mut deletion = None;
+        for (index, bp) in state.breakpoints.iter_mut().enumerate() {
             ui.horizontal(|ui| {
                 if ui.button("Remove").clicked() {
-                    deletion.push(*bp);
+                    deletion = Some(index);
+                }
+                if bp.condition.hits > 0 && ui.button("Reset Hits").clicked() {
+                    bp.condition.hits = 0;
                 }
 
-                ui.label(format!("{:04X}", bp));
+                ui.label(bp.describe());
             });
         }
 
-        state.breakpoints.retain(|x| !deletion.contains(x));
+        if let Some(index) = deletion {
+            state.breakpoints.remove(index);
+        }
+        Self::show_watchpoints(state, ui);
+        ui.separator();
+        Self::show_events(state, ui);
         ui.add_space(5.0);
         ui.horizontal(|ui| {
             ui.add_space(5.0);
             if ui.button("Add Breakpoint").clicked() {
                 self.show_message_box = true;
-                if let Some(cpu) = &state.cpu {
-                    self.breakpoint_input_buffer = format!("{:04x}", cpu.registers.pc);
+                if let Some(gameboy) = &state.gameboy {
+                    let cpu = &gameboy.cpu;
+                    self.breakpoint_input_buffer = format!("{:02x}:{:04x}", cpu.get_current_bank(), cpu.registers.pc);
                 }
             }
         });
@@ -78,13 +213,36 @@ impl Window for Breakpoints {
 
                 match self.breakpoint_type {
                     BreakpointType::Address => {
-                        ui.label("The address of the breakpoint:");
+                        ui.label("The address of the breakpoint, with an optional bank (05:4A10):");
                         ui.text_edit_singleline(&mut self.breakpoint_input_buffer);
                     }
                     BreakpointType::Instruction => {
-                        ui.label("The instruction opcode of the breakpoint:");
+                        ui.label("The instruction opcode of the breakpoint (D9, CB 7C):");
                         ui.text_edit_singleline(&mut self.breakpoint_input_buffer);
                     }
+                    BreakpointType::Watchpoint => {
+                        ui.horizontal(|ui| {
+                            for kind in WatchKind::ALL {
+                                ui.radio_value(&mut self.watch_kind, kind, kind.name());
+                            }
+                        });
+                        ui.label("The address or range (C000-C0FF) to watch:");
+                        ui.text_edit_singleline(&mut self.breakpoint_input_buffer);
+                        ui.label("Only stop on this value (optional):");
+                        ui.text_edit_singleline(&mut self.watch_value_buffer);
+                    }
+                    BreakpointType::Condition => {
+                        ui.label("Checked before every instruction.");
+                    }
+                }
+                ui.label("Condition, like A == $3F && [wPlayerHP] < 10 (optional):");
+                ui.text_edit_singleline(&mut self.condition_buffer);
+                ui.label("Break from this hit on (optional):");
+                ui.text_edit_singleline(&mut self.hit_buffer);
+                ui.label("Log this instead of stopping, like hp={[HL]} (optional):");
+                ui.text_edit_singleline(&mut self.log_buffer);
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
                 }
                 ui.separator();
                 Sides::new().show(
@@ -92,16 +250,21 @@ impl Window for Breakpoints {
                     |ui| {},
                     |ui| {
                         if Button::new("Add").min_size([50.0, 0.0].into()).ui(ui).clicked() {
-                            let Ok(addr) = u16::from_str_radix(&self.breakpoint_input_buffer, 16) else {
+                            if let Err(e) = self.add(state) {
+                                self.error = Some(e.to_string());
                                 return;
-                            };
-
-                            state.breakpoints.push(addr);
+                            }
                             self.breakpoint_input_buffer.clear();
+                            self.watch_value_buffer.clear();
+                            self.condition_buffer.clear();
+                            self.hit_buffer.clear();
+                            self.log_buffer.clear();
+                            self.error = None;
                             self.show_message_box = false;
                         }
                         if Button::new("Close").min_size([50.0, 0.0].into()).ui(ui).clicked() {
                             self.breakpoint_input_buffer.clear();
+                            self.error = None;
                             self.show_message_box = false;
                         }
                     },
@@ -1,6 +1,6 @@
-use crate::disassembler::LineType;
+use gb_core::disassembler::LineType;
 use crate::ui::windows::Window;
-use crate::ui::State;
+use crate::ui::{Breakpoint, BreakpointTrigger, State};
 use eframe::egui::scroll_area::ScrollAreaOutput;
 use eframe::egui::{Rect, Response, RichText, ScrollArea, Sense, TextStyle, TextWrapMode, Ui, Vec2, WidgetInfo, WidgetText, WidgetType};
 use eframe::emath::{Align, Pos2};
@@ -137,7 +137,8 @@ impl Disassembly {
 
 impl Window for Disassembly {
     fn show(&mut self, state: &mut State, ui: &mut Ui) {
-        let cpu = if let Some(cpu) = &mut state.cpu {
+        let cpu = if let Some(gameboy) = &mut state.gameboy {
+            let cpu = &mut gameboy.cpu;
             cpu
         } else {
             return;
@@ -152,6 +153,8 @@ impl Window for Disassembly {
 
         const LABEL_HEIGHT: f32 = 19.5;
         let height = ui.available_height();
+        // Runs after the view, which holds on to the CPU
+        let mut run_to = None;
         let output = ScrollArea::vertical()
             .id_salt("disassembly")
             .auto_shrink(false)
@@ -204,7 +207,7 @@ impl Window for Disassembly {
                                 let widget_text: WidgetText =
                                     (if let LineType::Label(_) = line.line_type {
                                         line.text.clone().into()
-                                    } else if state.breakpoints.contains(&line.address) {
+                                    } else if state.breakpoints.iter().any(|bp| bp.trigger.is_at(line.bank, line.address)) {
                                         RichText::new(text).color(Color32::LIGHT_RED).into()
                                     } else if line.address == cpu.registers.pc {
                                         RichText::new(text).color(Color32::LIGHT_GREEN).into()
@@ -232,18 +235,27 @@ impl Window for Disassembly {
 
                                 response.context_menu(|ui| {
                                     ui.set_width(200.0);
-                                    let has_breakpoint = state.breakpoints.contains(&line.address);
+                                    let has_breakpoint =
+                                        state.breakpoints.iter().any(|bp| bp.trigger.is_at(line.bank, line.address));
                                     if has_breakpoint {
                                         if ui.button("Remove Breakpoint").clicked() {
-                                            state.breakpoints.retain(|x| *x != line.address);
+                                            state.breakpoints.retain(|x| !x.trigger.is_at(line.bank, line.address));
                                             ui.close_menu();
                                         }
                                     } else {
                                         if ui.button("Add Breakpoint").clicked() {
-                                            state.breakpoints.push(line.address);
+                                            let trigger = BreakpointTrigger::Address {
+                                                address: line.address,
+                                                bank: Some(line.bank),
+                                            };
+                                            state.breakpoints.push(Breakpoint::new(trigger));
                                             ui.close_menu();
                                         }
                                     }
+                                    if ui.button("Run to Cursor").clicked() {
+                                        run_to = Some((line.bank, line.address));
+                                        ui.close_menu();
+                                    }
                                     if ui.button("Copy").clicked() {
                                         ui.output_mut(|writer| {
                                             writer.copied_text = line.text.to_string();
@@ -280,5 +292,8 @@ impl Window for Disassembly {
                 });
             });
         self.scroll_area_output = Some(output);
+        if let Some((bank, address)) = run_to {
+            state.run_to(bank, address);
+        }
     }
 }
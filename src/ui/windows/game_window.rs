@@ -1,10 +1,10 @@
-use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gb_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::audio::SyncMode;
 use crate::ui::windows::Window;
-use crate::ui::State;
+use crate::ui::{State, VideoStep};
 use eframe::egui::widgets::Image;
 use eframe::egui::{self, Id, Modal};
-use eframe::egui::{Button, DragValue, Ui, Widget};
-use eframe::epaint::textures::TextureOptions;
+use eframe::egui::{Button, Color32, DragValue, Ui, Widget};
 use std::time::Instant;
 
 pub struct GameWindow {
@@ -13,6 +13,8 @@ pub struct GameWindow {
     pub fullscreen: bool,
     pub fullscreen_scale: f32,
     pub normal_scale: f32,
+    // Line for Run to LY
+    target_ly: u8,
 }
 
 impl GameWindow {
@@ -23,6 +25,7 @@ impl GameWindow {
             fullscreen: false,
             fullscreen_scale: 7.0,
             normal_scale: 2.0,
+            target_ly: 0,
         }
     }
 }
@@ -32,26 +35,152 @@ const ONE_SECOND_IN_CYCLES: usize = 4190000;
 const ONE_FRAME_IN_CYCLES: usize = 70224;
 
 impl GameWindow {
+    // Cycles that bring the audio buffer up to the target latency, None when audio can not pace emulation
+    fn audio_sync_cycles(&self, state: &State, video_cycles: f64) -> Option<f64> {
+        if state.sync_mode != SyncMode::Audio || self.emulation_speed != 1.0 {
+            return None;
+        }
+        let gameboy = state.gameboy.as_ref()?;
+        let sound = &gameboy.cpu.mmu.sound;
+        let buffered = gameboy.audio_player().buffered_samples()?;
+        if !sound.is_on() {
+            return None;
+        }
+        let sample_rate = gameboy.audio_player().samples_rate();
+        let queued = buffered + sound.unmixed_samples();
+        let missing = state.rate_control.target_samples(sample_rate).saturating_sub(queued);
+        let cycles = missing as f64 * ONE_SECOND_IN_CYCLES as f64 / (sample_rate as f64 * sound.rate_adjustment());
+        // Catch up quickly, but do not race ahead when the device stops consuming samples
+        Some(cycles.min(video_cycles * 2.0))
+    }
+
+    // Keeps the audio buffer around the target latency through small changes of the output rate,
+    // only when syncing to audio. Video sync plays at the nominal rate.
+    fn update_rate_control(state: &mut State) {
+        let Some(gameboy) = &mut state.gameboy else {
+            return;
+        };
+        if state.sync_mode != SyncMode::Audio {
+            gameboy.cpu.mmu.sound.set_rate_adjustment(1.0);
+            return;
+        }
+        let player = gameboy.audio_player();
+        if let Some(buffered) = player.buffered_samples() {
+            let sample_rate = player.samples_rate();
+            let sound = &mut gameboy.cpu.mmu.sound;
+            let ratio = state.rate_control.ratio(buffered + sound.unmixed_samples(), sample_rate);
+            sound.set_rate_adjustment(ratio);
+        }
+    }
+
+    fn toggle_running(state: &mut State) {
+        state.running = !state.running;
+        state.run_target = None;
+        state.cycles_elapsed_in_frame += state.step() as usize;
+    }
+
+    fn handle_shortcuts(state: &mut State, ui: &mut Ui) {
+        if ui.ctx().wants_keyboard_input() || state.gameboy.is_none() {
+            return;
+        }
+        let (shift, run, step, step_over) = ui.input(|i| {
+            (
+                i.modifiers.shift,
+                i.key_pressed(egui::Key::F9),
+                i.key_pressed(egui::Key::F7),
+                i.key_pressed(egui::Key::F8),
+            )
+        });
+        if run {
+            Self::toggle_running(state);
+        } else if !state.running {
+            if step {
+                state.cycles_elapsed_in_frame += state.step() as usize;
+            } else if step_over && shift {
+                state.step_out();
+            } else if step_over {
+                state.step_over();
+            }
+        }
+    }
+
+    fn show_video_buttons(&mut self, state: &mut State, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add_space(5.0);
+            ui.add_enabled_ui(!state.running, |ui| {
+                if ui.button("Step Scanline").clicked() {
+                    state.run_video_step(VideoStep::Scanline);
+                }
+                if ui.button("Run to VBlank").clicked() {
+                    state.run_video_step(VideoStep::VBlank);
+                }
+                if ui.button("Frame Advance").clicked() {
+                    state.run_video_step(VideoStep::Frame);
+                }
+                if ui.button("Run to LY").clicked() {
+                    state.run_video_step(VideoStep::Line(self.target_ly));
+                }
+                ui.add(DragValue::new(&mut self.target_ly).range(0..=153));
+            });
+            if let Some(gameboy) = &state.gameboy {
+                ui.label(format!("LY {}", gameboy.cpu.mmu.ppu.ly()));
+                if gameboy.cpu.is_locked_up() {
+                    ui.colored_label(Color32::RED, "CPU locked up, reset to continue");
+                }
+            }
+        });
+    }
+
     fn show_control_buttons(&mut self, state: &mut State, ui: &mut Ui) {
         ui.horizontal(|ui| {
             ui.add_space(5.0);
             let run_btn = Button::new(if state.running { "Stop" } else { "Run" })
                 .min_size([50.0, 0.0].into())
                 .ui(ui);
-            if run_btn.clicked() {
-                state.running = !state.running;
-                state.cycles_elapsed_in_frame += state.step() as usize;
+            if run_btn.on_hover_text("F9").clicked() {
+                Self::toggle_running(state);
             }
 
             let step_btn = Button::new("Step").min_size([50.0, 0.0].into()).ui(ui);
-            if step_btn.clicked() {
+            if step_btn.on_hover_text("F7").clicked() {
                 state.cycles_elapsed_in_frame += state.step() as usize;
             }
 
+            let step_over_btn = Button::new("Step Over").min_size([50.0, 0.0].into()).ui(ui);
+            if step_over_btn.on_hover_text("F8").clicked() {
+                state.step_over();
+            }
+
+            let step_out_btn = Button::new("Step Out").min_size([50.0, 0.0].into()).ui(ui);
+            if step_out_btn.on_hover_text("Shift+F8").clicked() {
+                state.step_out();
+            }
+
+            // Travelling back would desync the movie from its frame counter
+            let can_travel_back = state.rewind.snapshot_count() > 0 && state.movie.is_none();
+            let step_back_btn = ui.add_enabled(can_travel_back, Button::new("Step Back").min_size([50.0, 0.0].into()));
+            if step_back_btn.clicked() {
+                state.running = false;
+                state.step_back();
+            }
+
+            let reverse_continue_btn =
+                ui.add_enabled(can_travel_back, Button::new("Reverse Continue").min_size([50.0, 0.0].into()));
+            if reverse_continue_btn.clicked() {
+                state.running = false;
+                state.reverse_continue();
+            }
+
             let reset_btn = Button::new("Reset").min_size([50.0, 0.0].into()).ui(ui);
             if reset_btn.clicked() {
-                if let Some(cpu) = &mut state.cpu {
+                if let Some(movie) = &mut state.movie {
+                    // Keep the reset on a frame boundary so it can be replayed
+                    movie.request_reset();
+                } else if let Some(gameboy) = &mut state.gameboy {
+                    let cpu = &mut gameboy.cpu;
                     cpu.reset();
+                    state.rewind.clear();
+                    state.input_log.clear();
                     state.extra_targets.clear();
                     state.disassembler.disassembly.clear();
                     state.disassembler.disassemble(cpu);
@@ -81,51 +210,58 @@ impl GameWindow {
 
 impl Window for GameWindow {
     fn show(&mut self, state: &mut State, ui: &mut Ui) {
+        Self::handle_shortcuts(state, ui);
         let input = ui.ctx().input(|i| i.clone());
-        if let Some(cpu) = &mut state.cpu {
-            cpu.mmu.joypad.up = input.key_down(egui::Key::ArrowUp);
-            cpu.mmu.joypad.down = input.key_down(egui::Key::ArrowDown);
-            cpu.mmu.joypad.left = input.key_down(egui::Key::ArrowLeft);
-            cpu.mmu.joypad.right = input.key_down(egui::Key::ArrowRight);
-            cpu.mmu.joypad.a = input.key_down(egui::Key::X);
-            cpu.mmu.joypad.b = input.key_down(egui::Key::Z);
-            cpu.mmu.joypad.start = input.key_down(egui::Key::Enter);
-            cpu.mmu.joypad.select = input.key_down(egui::Key::Space);
-        }
+        let buttons = [
+            egui::Key::ArrowRight,
+            egui::Key::ArrowLeft,
+            egui::Key::ArrowUp,
+            egui::Key::ArrowDown,
+            egui::Key::X,
+            egui::Key::Z,
+            egui::Key::Space,
+            egui::Key::Enter,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |buttons, (i, key)| buttons | (input.key_down(*key) as u8) << i);
+        state.set_input(buttons);
 
-        if state.running && state.cpu.is_some() {
+        // Holding the rewind key plays the emulation backwards, one snapshot per UI frame
+        let rewinding =
+            input.key_down(egui::Key::Backspace) && !ui.ctx().wants_keyboard_input() && state.movie.is_none();
+        if rewinding {
+            state.rewind();
+            self.now = Instant::now();
+        } else if state.running && state.gameboy.is_some() {
             let time_delta = self.now.elapsed().subsec_nanos() as f32 * self.emulation_speed;
             self.now = Instant::now();
             let delta = time_delta as f64 / ONE_SECOND_IN_MICROS as f64;
-            let cycles_to_run = delta * ONE_SECOND_IN_CYCLES as f64;
+            let mut cycles_to_run = delta * ONE_SECOND_IN_CYCLES as f64;
+            if let Some(cycles) = self.audio_sync_cycles(state, cycles_to_run) {
+                cycles_to_run = cycles;
+            }
 
             let mut cycles_elapsed = 0;
             while cycles_elapsed <= cycles_to_run as usize {
-                if let Some(cpu) = &mut state.cpu {
-                    if state.breakpoints.contains(&cpu.registers.pc) || !state.running {
-                        state.running = false;
-                        state.cycles_elapsed_in_frame += cycles_elapsed;
-                        break;
-                    }
+                if state.check_breakpoints() || state.reached_run_target() || !state.running {
+                    state.running = false;
+                    state.run_target = None;
+                    state.cycles_elapsed_in_frame += cycles_elapsed;
+                    break;
                 }
                 cycles_elapsed += state.step() as usize;
             }
             state.cycles_elapsed_in_frame += cycles_elapsed;
+            Self::update_rate_control(state);
         }
 
         // Render the frame to a texture
-        if state.cycles_elapsed_in_frame >= ONE_FRAME_IN_CYCLES {
-            if let Some(cpu) = &mut state.cpu {
-                if cpu.mmu.ppu.screen_buffer_updated {
-                    let color_image = egui::ColorImage::from_rgb(
-                        [SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize],
-                        &cpu.mmu.ppu.screen_buffer,
-                    );
-                    state.texture.set(color_image, TextureOptions::NEAREST);
-                    state.cycles_elapsed_in_frame = 0;
-                    cpu.mmu.ppu.screen_buffer_updated = false;
-                }
-            }
+        if state.cycles_elapsed_in_frame >= ONE_FRAME_IN_CYCLES
+            && state.gameboy.as_ref().is_some_and(|gameboy| gameboy.cpu.mmu.ppu.screen_buffer_updated)
+        {
+            state.update_texture();
+            state.cycles_elapsed_in_frame = 0;
         }
 
         if !self.fullscreen {
@@ -133,6 +269,7 @@ impl Window for GameWindow {
                 .fit_to_exact_size([SCREEN_WIDTH as f32 * 2.0, SCREEN_HEIGHT as f32 * 2.0].into())
                 .ui(ui);
             self.show_control_buttons(state, ui);
+            self.show_video_buttons(state, ui);
         } else {
             let modal = Modal::new(Id::new("Game")).show(ui.ctx(), |ui| {
                 ui.set_width(SCREEN_WIDTH as f32 * self.fullscreen_scale);
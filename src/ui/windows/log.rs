@@ -0,0 +1,65 @@
+use crate::show_error;
+use crate::ui::windows::Window;
+use crate::ui::State;
+use eframe::egui;
+use eframe::egui::{ScrollArea, TextStyle};
+
+/// Shows the messages of logpoints, see the Breakpoints pane.
+pub struct Log {
+    follow: bool,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Self { follow: true }
+    }
+
+    fn log_file_dialog(state: &mut State) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Log to File")
+            .add_filter("Text File", &["txt", "log"])
+            .show_save_single_file()
+        {
+            if let Err(e) = state.debug_log.start_file(&path) {
+                show_error(&format!("Could not create the log file: {}", e));
+            }
+        }
+    }
+}
+
+impl Window for Log {
+    fn show(&mut self, state: &mut State, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                state.debug_log.entries.clear();
+            }
+            if state.debug_log.is_logging_to_file() {
+                if ui.button("Stop Logging to File").clicked() {
+                    if let Err(e) = state.debug_log.stop_file() {
+                        show_error(&format!("Could not save the log file: {}", e));
+                    }
+                }
+            } else if ui.button("Log to File...").clicked() {
+                Self::log_file_dialog(state);
+            }
+            ui.checkbox(&mut self.follow, "Follow");
+        });
+        ui.separator();
+
+        let entries = &state.debug_log.entries;
+        if entries.is_empty() {
+            ui.label("Add a breakpoint with a log message to trace without stopping");
+            return;
+        }
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        ScrollArea::vertical()
+            .id_salt("debug_log")
+            .auto_shrink(false)
+            .stick_to_bottom(self.follow)
+            .show_rows(ui, row_height, entries.len(), |ui, rows| {
+                for entry in entries.range(rows) {
+                    ui.monospace(format!("{:>8} {:>12}  {}", entry.frame, entry.cycle, entry.text));
+                }
+            });
+    }
+}
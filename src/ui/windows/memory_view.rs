@@ -46,7 +46,8 @@ impl MemoryView {
             .auto_shrink(false)
             .drag_to_scroll(false)
             .show(ui, |ui| {
-                if let Some(cpu) = &mut state.cpu {
+                if let Some(gameboy) = &mut state.gameboy {
+                    let cpu = &mut gameboy.cpu;
                     for row_addr in (start..=end).step_by(BYTES_PER_LINE) {
                         let distance =
                             ((row_addr as i64 - focussed_row_addr as i64).abs() / 16) as usize;
@@ -161,9 +162,10 @@ impl MemoryView {
                 bottom: 5,
             })
             .show(ui, |ui| {
-                let Some(cpu) = &mut state.cpu else {
+                let Some(gameboy) = &mut state.gameboy else {
                     return;
                 };
+                let cpu = &mut gameboy.cpu;
 
                 ScrollArea::vertical()
                     .auto_shrink(false)
@@ -187,7 +189,8 @@ impl MemoryView {
     fn show_memory_range(&mut self, state: &mut State, ui: &mut egui::Ui, start: u16, end: u16, shrink: bool) {
         const BYTES_PER_LINE: usize = 0x10;
 
-        if let Some(cpu) = &mut state.cpu {
+        if let Some(gameboy) = &mut state.gameboy {
+            let cpu = &mut gameboy.cpu;
             ui.horizontal(|ui| {
                 ui.add_space(5.0);
                 ui.label("addr");
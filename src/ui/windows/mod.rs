@@ -10,6 +10,14 @@ mod memory_view;
 pub use memory_view::*;
 mod tile_map_viewer;
 pub use tile_map_viewer::*;
+mod audio;
+pub use audio::*;
+mod apu_viewer;
+pub use apu_viewer::*;
+mod tracker;
+pub use tracker::*;
+mod log;
+pub use log::*;
 
 use crate::ui::State;
 use eframe::egui;
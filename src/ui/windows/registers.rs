@@ -35,7 +35,8 @@ impl Window for Registers {
                 ui.label("C:");
             });
 
-            if let Some(cpu) = &state.cpu {
+            if let Some(gameboy) = &state.gameboy {
+                let cpu = &gameboy.cpu;
                 ui.vertical(|ui| {
                     ui.label(format!("{:02X}", cpu.registers.a));
                     ui.label(format!("{:02X}", cpu.registers.b));
@@ -4,9 +4,9 @@ use eframe::emath::Pos2;
 use eframe::epaint::{Color32, Stroke};
 use eframe::epaint::textures::TextureOptions;
 use eframe::egui::{ComboBox, Frame, Image, Rect, TextureHandle, Ui, Widget};
-use crate::cpu::CPU;
-use crate::gbmode::GbMode;
-use crate::ppu::PPU;
+use gb_core::cpu::CPU;
+use gb_core::gbmode::GbMode;
+use gb_core::ppu::PPU;
 use crate::ui::windows::Window;
 use crate::ui::State;
 
@@ -210,7 +210,8 @@ impl TileMapViewer {
     }
 
     pub fn show_tiles(&mut self, state: &mut State, ui: &mut Ui) {
-        if let Some(cpu) = &state.cpu {
+        if let Some(gameboy) = &state.gameboy {
+            let cpu = &gameboy.cpu;
             ui.horizontal(|ui| {
                 ui.add_space(5.0);
                 ui.checkbox(&mut self.show_grid, "Show Grid");
@@ -302,7 +303,8 @@ impl TileMapViewer {
             });
         });
 
-        if let Some(cpu) = &mut state.cpu {
+        if let Some(gameboy) = &mut state.gameboy {
+            let cpu = &mut gameboy.cpu;
             let x = ui.cursor().min.x + 5.0 + (cpu.mmu.ppu.scx as f32 * (TILE_IMAGE_SIZE / 8.0));
             let y = ui.cursor().min.y + (cpu.mmu.ppu.scy as f32 * (TILE_IMAGE_SIZE / 8.0));
             ui.spacing_mut().item_spacing = [0.0, 0.0].into();
@@ -378,7 +380,8 @@ impl TileMapViewer {
 
 impl Window for TileMapViewer {
     fn show(&mut self, state: &mut State, ui: &mut Ui) {
-        if let Some(cpu) = &mut state.cpu {
+        if let Some(gameboy) = &mut state.gameboy {
+            let cpu = &mut gameboy.cpu;
             self.update_textures(cpu);
         }
 
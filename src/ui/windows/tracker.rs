@@ -0,0 +1,195 @@
+use crate::show_error;
+use crate::ui::windows::{Window, CHANNEL_NAMES};
+use crate::ui::State;
+use eframe::egui;
+use eframe::egui::{Color32, DragValue, Grid, Pos2, Rect, ScrollArea, Sense, Stroke, Ui, Vec2};
+use gb_core::io::sound::{midi_note, note_name};
+use gb_core::tracker::{MusicLog, Note, NoteEventKind};
+
+const CHANNEL_COLORS: [Color32; 4] = [
+    Color32::from_rgb(230, 120, 90),
+    Color32::from_rgb(230, 200, 80),
+    Color32::from_rgb(90, 170, 230),
+    Color32::from_rgb(160, 160, 160),
+];
+const ROW_HEIGHT: f32 = 4.0;
+const EVENTS_SHOWN: usize = 200;
+
+pub struct Tracker {
+    // The log after logging was stopped, kept for viewing and export
+    log: Option<MusicLog>,
+    pixels_per_frame: f32,
+    follow: bool,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self {
+            log: None,
+            pixels_per_frame: 2.0,
+            follow: true,
+        }
+    }
+
+    fn export_dialog(log: &MusicLog) {
+        if let Ok(Some(path)) = native_dialog::FileDialog::new()
+            .set_title("Export MIDI")
+            .add_filter("MIDI File", &["mid"])
+            .show_save_single_file()
+        {
+            if let Err(e) = log.save_midi(&path.with_extension("mid")) {
+                show_error(&format!("Could not export MIDI: {}", e));
+            }
+        }
+    }
+
+    fn show_piano_roll(&self, ui: &mut Ui, notes: &[Note]) {
+        let (Some(lowest), Some(highest)) = (
+            notes.iter().map(|note| note.note).min(),
+            notes.iter().map(|note| note.note).max(),
+        ) else {
+            ui.label("No notes yet");
+            return;
+        };
+        let first_frame = notes[0].start_frame;
+        let last_frame = notes.iter().map(|note| note.end_frame).max().unwrap_or(first_frame);
+        let rows = (highest - lowest) as f32 + 1.0;
+
+        let mut scroll = ScrollArea::horizontal()
+            .id_salt("piano_roll")
+            .auto_shrink([false, true]);
+        if self.follow {
+            scroll = scroll.stick_to_right(true);
+        }
+        scroll.show(ui, |ui| {
+            let width = (last_frame - first_frame) as f32 * self.pixels_per_frame;
+            let size = Vec2::new(width.max(ui.available_width()), rows * ROW_HEIGHT);
+            let (response, painter) = ui.allocate_painter(size, Sense::hover());
+            let rect = response.rect;
+            painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+            // Mark every C
+            for note in lowest..=highest {
+                if note % 12 == 0 {
+                    let y = rect.bottom() - (note - lowest) as f32 * ROW_HEIGHT;
+                    painter.hline(rect.x_range(), y, Stroke::new(1.0, ui.visuals().faint_bg_color));
+                }
+            }
+
+            for note in notes {
+                let left = rect.left() + (note.start_frame - first_frame) as f32 * self.pixels_per_frame;
+                let right = rect.left() + (note.end_frame - first_frame) as f32 * self.pixels_per_frame;
+                let bottom = rect.bottom() - (note.note - lowest) as f32 * ROW_HEIGHT;
+                let bar = Rect::from_min_max(
+                    Pos2::new(left, bottom - ROW_HEIGHT + 1.0),
+                    Pos2::new(right.max(left + 1.0), bottom),
+                );
+                let alpha = 80 + note.volume as u8 * 11;
+                let color = CHANNEL_COLORS[note.channel as usize];
+                painter.rect_filled(
+                    bar,
+                    0.0,
+                    Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), alpha),
+                );
+            }
+
+            if let Some(pointer) = response.hover_pos() {
+                let frame = first_frame + ((pointer.x - rect.left()) / self.pixels_per_frame) as u64;
+                let note = lowest as i32 + ((rect.bottom() - pointer.y) / ROW_HEIGHT) as i32;
+                response.on_hover_text(format!("Frame {}, {}", frame, note_name(note)));
+            }
+        });
+    }
+
+    fn show_events(ui: &mut Ui, log: &MusicLog) {
+        ScrollArea::vertical()
+            .id_salt("tracker_events")
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                Grid::new("tracker_events_grid").striped(true).show(ui, |ui| {
+                    ui.strong("Frame");
+                    ui.strong("Channel");
+                    ui.strong("Event");
+                    ui.strong("Note");
+                    ui.strong("Volume");
+                    ui.end_row();
+
+                    let skip = log.events.len().saturating_sub(EVENTS_SHOWN);
+                    for event in log.events.iter().skip(skip) {
+                        ui.label(format!("{}", event.frame()));
+                        ui.colored_label(
+                            CHANNEL_COLORS[event.channel as usize],
+                            CHANNEL_NAMES[event.channel as usize],
+                        );
+                        ui.label(match event.kind {
+                            NoteEventKind::Trigger => "Trigger",
+                            NoteEventKind::Frequency => "Frequency",
+                            NoteEventKind::Volume => "Volume",
+                            NoteEventKind::Stop => "Stop",
+                        });
+                        if event.channel == 3 || event.frequency_hz <= 0.0 {
+                            ui.label(format!("{:.0} Hz", event.frequency_hz));
+                        } else {
+                            let note = midi_note(event.frequency_hz).round() as i32;
+                            ui.label(format!("{} ({:.1} Hz)", note_name(note), event.frequency_hz));
+                        }
+                        ui.label(format!("{}", event.volume));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+impl Window for Tracker {
+    fn show(&mut self, state: &mut State, ui: &mut egui::Ui) {
+        let Some(gameboy) = &mut state.gameboy else {
+            return;
+        };
+        let sound = &mut gameboy.cpu.mmu.sound;
+
+        ui.horizontal(|ui| {
+            if sound.music_log.is_some() {
+                if ui.button("Stop Logging").clicked() {
+                    self.log = sound.music_log.take();
+                }
+            } else if ui.button("Start Logging").clicked() {
+                sound.music_log = Some(self.log.take().unwrap_or_default());
+            }
+            if ui.button("Clear").clicked() {
+                self.log = None;
+                if let Some(log) = &mut sound.music_log {
+                    log.clear();
+                }
+            }
+
+            let log = sound.music_log.as_ref().or(self.log.as_ref());
+            if ui
+                .add_enabled(log.is_some(), egui::Button::new("Export MIDI..."))
+                .clicked()
+            {
+                if let Some(log) = log {
+                    Self::export_dialog(log);
+                }
+            }
+            ui.checkbox(&mut self.follow, "Follow");
+            ui.add(
+                DragValue::new(&mut self.pixels_per_frame)
+                    .range(0.25..=16.0)
+                    .speed(0.05)
+                    .suffix(" px/frame"),
+            );
+        });
+
+        let Some(log) = sound.music_log.as_ref().or(self.log.as_ref()) else {
+            ui.label("Start logging to record the notes played by the APU");
+            return;
+        };
+        let notes = log.notes();
+        ui.label(format!("{} events, {} notes", log.events.len(), notes.len()));
+        self.show_piano_roll(ui, &notes);
+        ui.separator();
+        Self::show_events(ui, log);
+    }
+}